@@ -0,0 +1,105 @@
+//! Command line parsing.
+//!
+//! ```text
+//! srvstat [--config <path>] [--once]
+//! srvstat config check [--config <path>]
+//! srvstat --version
+//! ```
+
+use anyhow::bail;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Collect and publish metrics (the default).
+    Run,
+    /// Validate the configuration and print the effective values.
+    ConfigCheck,
+    /// Print the application version.
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub command: Command,
+    pub config_path: Option<PathBuf>,
+    /// Collect a single time instead of running as a daemon.
+    pub once: bool,
+}
+
+impl Cli {
+    /// Parses the arguments, without the program name.
+    pub fn parse<I>(args: I) -> anyhow::Result<Cli>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut cli = Cli {
+            command: Command::Run,
+            config_path: None,
+            once: false,
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--version" => cli.command = Command::Version,
+                "--once" => cli.once = true,
+                "--config" => match args.next() {
+                    Some(path) => cli.config_path = Some(PathBuf::from(path)),
+                    None => bail!("--config requires a path"),
+                },
+                "config" => match args.next().as_deref() {
+                    Some("check") => cli.command = Command::ConfigCheck,
+                    Some(other) => bail!("Unknown config subcommand: {}", other),
+                    None => bail!("config requires a subcommand (check)"),
+                },
+                other => bail!("Unknown argument: {}", other),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Cli> {
+        Cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_default() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.command, Command::Run);
+        assert_eq!(cli.config_path, None);
+        assert!(!cli.once);
+    }
+
+    #[test]
+    fn test_parse_run_options() {
+        let cli = parse(&["--config", "/etc/srvstat.toml", "--once"]).unwrap();
+        assert_eq!(cli.command, Command::Run);
+        assert_eq!(cli.config_path, Some(PathBuf::from("/etc/srvstat.toml")));
+        assert!(cli.once);
+    }
+
+    #[test]
+    fn test_parse_config_check() {
+        let cli = parse(&["config", "check", "--config", "srvstat.toml"]).unwrap();
+        assert_eq!(cli.command, Command::ConfigCheck);
+        assert_eq!(cli.config_path, Some(PathBuf::from("srvstat.toml")));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse(&["--version"]).unwrap().command, Command::Version);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["config"]).is_err());
+        assert!(parse(&["config", "show"]).is_err());
+        assert!(parse(&["--daemon"]).is_err());
+    }
+}
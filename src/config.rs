@@ -1,15 +1,1316 @@
-use anyhow::Context;
-use std::env::var;
+//! Application configuration.
+//!
+//! The configuration is read from an optional TOML file and every field can be
+//! overridden with a `SRVSTAT_<SECTION>_<FIELD>` environment variable, e.g.
+//! `SRVSTAT_MQTT_BROKER_URL` or `SRVSTAT_COLLECTION_INTERVAL`. List values are
+//! given comma separated (`SRVSTAT_COLLECTION_CATEGORIES=cpu,memory`), and map
+//! entries one per variable (`SRVSTAT_MQTT_DEADBAND_DISK=2`). The `[[alerts]]`
+//! and `[[watch]]` entries are replaced as a whole by a TOML inline array, e.g.
+//! `SRVSTAT_WATCH=[{name = "nginx", process = "nginx"}]`. The legacy
+//! `BROKER_URL` variable is still honored.
+//!
+//! ```toml
+//! [mqtt]
+//! broker_url = "ssl://broker:8883"
+//! discovery_prefix = "homeassistant"
+//! username = "srvstat"
+//! password_file = "/run/secrets/mqtt"
+//! ca_file = "/etc/ssl/certs/broker-ca.pem"
+//! heartbeat = 180
+//! deadband = { disk = 1, temperature = 0.5 }
+//!
+//! [collection]
+//! interval = 60
+//! categories = ["disk", "memory", "cpu", "swap", "load", "uptime"]
+//! sample_interval = 5
+//! sysfs_root = "/sys"
+//! procfs_root = "/proc"
+//!
+//! [cpu]
+//! per_core = true
+//!
+//! [disk]
+//! exclude_mounts = ["/boot/*"]
+//! exclude_fs_types = ["tmpfs", "overlay", "squashfs"]
+//!
+//! [network]
+//! exclude_interfaces = ["lo", "veth*", "docker*"]
+//!
+//! [processes]
+//! top = 5
+//!
+//! [cgroups]
+//! root = "/sys/fs/cgroup"
+//! include_cgroups = ["system.slice/*"]
+//!
+//! [prometheus]
+//! enabled = false
+//! listen = "0.0.0.0:9184"
+//!
+//! [influxdb]
+//! enabled = false
+//! url = "http://localhost:8086"
+//! org = "home"
+//! bucket = "srvstat"
+//!
+//! [console]
+//! enabled = false
+//!
+//! [file]
+//! enabled = false
+//! path = "/var/lib/srvstat/metrics.jsonl"
+//!
+//! [[alerts]]
+//! name = "disk_full"
+//! category = "disk"
+//! warn = 80
+//! crit = 90
+//! duration = 300
+//! hysteresis = 5
+//!
+//! [[watch]]
+//! name = "nginx"
+//! process = "nginx"
+//! ```
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::domain::alerts::models::AlertRule;
+use crate::domain::filter::Filter;
+use crate::domain::ha::models::DISCOVERY_PREFIX;
+use crate::domain::metrics::models::Category;
+use crate::domain::watch::ProcessMatcher;
+use anyhow::{bail, ensure, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+
+/// Prefix of the environment variables overriding the configuration.
+const ENV_PREFIX: &str = "SRVSTAT_";
+/// Legacy environment variable for the broker URL, still honored.
+const LEGACY_BROKER_URL: &str = "BROKER_URL";
+/// Where sysfs is usually mounted.
+pub const SYSFS_ROOT: &str = "/sys";
+/// Where procfs is usually mounted.
+pub const PROCFS_ROOT: &str = "/proc";
+/// Where the cgroup v2 hierarchy is usually mounted.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub broker_url: String,
+    pub mqtt: MqttConfig,
+    pub collection: CollectionConfig,
+    pub cpu: CpuConfig,
+    pub disk: DiskConfig,
+    pub network: NetworkConfig,
+    pub processes: ProcessesConfig,
+    pub cgroups: CgroupsConfig,
+    pub prometheus: PrometheusConfig,
+    pub influxdb: InfluxConfig,
+    pub console: ConsoleConfig,
+    pub file: FileConfig,
+    pub alerts: Vec<AlertRule>,
+    pub watch: Vec<WatchConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Publishes to the broker when `broker_url` is set.
+    pub enabled: bool,
+    /// Broker URL, e.g. `tcp://localhost:1883`.
+    pub broker_url: Option<String>,
+    /// Home Assistant discovery prefix the sensors are published under.
+    pub discovery_prefix: String,
+    /// Fixed client ID; the broker assigns one when unset.
+    pub client_id: Option<String>,
+    /// Keep-alive interval, in seconds.
+    #[serde(with = "duration_secs")]
+    pub keep_alive: Duration,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// File holding the password, as an alternative to `password`.
+    pub password_file: Option<PathBuf>,
+    /// CA bundle used to verify the broker certificate.
+    pub ca_file: Option<PathBuf>,
+    /// Client certificate (PEM) for mutual TLS, used with `client_key`.
+    pub client_cert: Option<PathBuf>,
+    /// Private key (PEM) of `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// Shortest delay before reconnecting to a lost broker, in seconds.
+    #[serde(with = "duration_secs")]
+    pub reconnect_min_delay: Duration,
+    /// Longest delay between reconnection attempts, in seconds.
+    #[serde(with = "duration_secs")]
+    pub reconnect_max_delay: Duration,
+    /// Number of state and alert messages kept while the broker is unreachable.
+    pub queue_size: usize,
+    /// File the unsent messages are saved to, so they survive a restart.
+    pub queue_file: Option<PathBuf>,
+    /// Time after which an unchanged state is published again, in seconds.
+    /// The sensors expire after the heartbeat plus two collection intervals.
+    #[serde(with = "duration_secs")]
+    pub heartbeat: Duration,
+    /// Smallest change worth publishing per category: in percentage points
+    /// for the usage categories (disk, memory, cpu, swap), in the unit of each
+    /// quantity for the others (e.g. B/s for network rates, °C for
+    /// temperatures). Any change is published for the other categories.
+    pub deadband: BTreeMap<Category, f64>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            enabled: true,
+            broker_url: None,
+            discovery_prefix: DISCOVERY_PREFIX.to_string(),
+            client_id: None,
+            keep_alive: Duration::from_secs(20),
+            username: None,
+            password: None,
+            password_file: None,
+            ca_file: None,
+            client_cert: None,
+            client_key: None,
+            reconnect_min_delay: Duration::from_secs(1),
+            reconnect_max_delay: Duration::from_secs(60),
+            queue_size: 1000,
+            queue_file: None,
+            heartbeat: Duration::from_secs(180),
+            deadband: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionConfig {
+    /// Time between two collections, in seconds.
+    #[serde(with = "duration_secs")]
+    pub interval: Duration,
+    /// Categories collected on every run.
+    pub categories: Vec<Category>,
+    /// Time between two samples within an interval, in seconds. Each
+    /// interval then also reports the min, max, mean and 95th percentile of
+    /// the sampled percentages. Disabled when 0.
+    #[serde(with = "duration_secs")]
+    pub sample_interval: Duration,
+    /// Where sysfs is mounted, e.g. the host's `/sys` mounted in a container.
+    pub sysfs_root: PathBuf,
+    /// Where procfs is mounted, read for the pressure stall information.
+    pub procfs_root: PathBuf,
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        CollectionConfig {
+            interval: Duration::from_secs(60),
+            categories: vec![
+                Category::Disk,
+                Category::Memory,
+                Category::Cpu,
+                Category::Swap,
+            ],
+            sample_interval: Duration::ZERO,
+            sysfs_root: PathBuf::from(SYSFS_ROOT),
+            procfs_root: PathBuf::from(PROCFS_ROOT),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CpuConfig {
+    /// Report the usage of every core alongside the global value.
+    pub per_core: bool,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig { per_core: true }
+    }
+}
+
+/// Selects the filesystems reported by the disk category. Patterns match
+/// exactly, or by prefix when they end with `*`; empty include lists include
+/// everything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskConfig {
+    pub include_mounts: Vec<String>,
+    pub exclude_mounts: Vec<String>,
+    pub include_fs_types: Vec<String>,
+    pub exclude_fs_types: Vec<String>,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        DiskConfig {
+            include_mounts: Vec::new(),
+            exclude_mounts: Vec::new(),
+            include_fs_types: Vec::new(),
+            exclude_fs_types: vec![
+                "tmpfs".to_string(),
+                "overlay".to_string(),
+                "squashfs".to_string(),
+            ],
+        }
+    }
+}
+
+impl DiskConfig {
+    pub fn mount_filter(&self) -> Filter {
+        Filter::new(self.include_mounts.clone(), self.exclude_mounts.clone())
+    }
+
+    pub fn fs_type_filter(&self) -> Filter {
+        Filter::new(self.include_fs_types.clone(), self.exclude_fs_types.clone())
+    }
+}
+
+/// Interfaces reported by the network category.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub include_interfaces: Vec<String>,
+    pub exclude_interfaces: Vec<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            include_interfaces: Vec::new(),
+            exclude_interfaces: vec!["lo".to_string(), "veth*".to_string(), "docker*".to_string()],
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn interface_filter(&self) -> Filter {
+        Filter::new(
+            self.include_interfaces.clone(),
+            self.exclude_interfaces.clone(),
+        )
+    }
+}
+
+/// Ranking reported by the processes category.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessesConfig {
+    /// Number of processes reported by CPU and by memory usage.
+    pub top: usize,
+}
+
+impl Default for ProcessesConfig {
+    fn default() -> Self {
+        ProcessesConfig { top: 5 }
+    }
+}
+
+/// Cgroups reported by the cgroups category, by their path below the root,
+/// e.g. `system.slice/nginx.service`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CgroupsConfig {
+    /// Where the cgroup v2 hierarchy is mounted.
+    pub root: PathBuf,
+    pub include_cgroups: Vec<String>,
+    pub exclude_cgroups: Vec<String>,
+}
+
+impl Default for CgroupsConfig {
+    fn default() -> Self {
+        CgroupsConfig {
+            root: PathBuf::from(CGROUP_ROOT),
+            include_cgroups: Vec::new(),
+            exclude_cgroups: Vec::new(),
+        }
+    }
+}
+
+impl CgroupsConfig {
+    pub fn cgroup_filter(&self) -> Filter {
+        Filter::new(self.include_cgroups.clone(), self.exclude_cgroups.clone())
+    }
+}
+
+/// A process that must be running, matched by exactly one of its name, the
+/// path of its executable or a regular expression on its command line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Identifies the process in sensors and series, e.g. `nginx`.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+}
+
+impl WatchConfig {
+    pub fn matcher(&self) -> anyhow::Result<ProcessMatcher> {
+        match (&self.process, &self.exe, &self.cmdline) {
+            (Some(name), None, None) => Ok(ProcessMatcher::Name(name.clone())),
+            (None, Some(exe), None) => Ok(ProcessMatcher::Exe(exe.clone())),
+            (None, None, Some(cmdline)) => regex::Regex::new(cmdline)
+                .map(ProcessMatcher::Cmdline)
+                .with_context(|| format!("watch {} cmdline is not a valid regex", self.name)),
+            _ => bail!(
+                "watch {} needs exactly one of process, exe or cmdline",
+                self.name
+            ),
+        }
+    }
+}
+
+/// Prometheus exporter serving the latest values on `/metrics`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrometheusConfig {
+    pub enabled: bool,
+    /// Address the HTTP endpoint listens on.
+    pub listen: String,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        PrometheusConfig {
+            enabled: false,
+            listen: "0.0.0.0:9184".to_string(),
+        }
+    }
+}
+
+/// InfluxDB writer, sending line protocol batches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    pub enabled: bool,
+    /// Server URL: `http(s)://host:8086` for the v2 write endpoint, or
+    /// `udp://host:8089` for a UDP listener.
+    pub url: String,
+    pub org: Option<String>,
+    pub bucket: String,
+    /// API token, sent as `Authorization: Token <token>`.
+    pub token: Option<String>,
+    /// Number of lines sent together.
+    pub batch_size: usize,
+    /// Lines kept while InfluxDB is unreachable, the oldest being dropped.
+    pub buffer_size: usize,
+    /// HTTP request timeout, in seconds.
+    #[serde(with = "duration_secs")]
+    pub timeout: Duration,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        InfluxConfig {
+            enabled: false,
+            url: "http://localhost:8086".to_string(),
+            org: None,
+            bucket: "srvstat".to_string(),
+            token: None,
+            batch_size: 100,
+            buffer_size: 10000,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Prints every metric on the standard output.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsoleConfig {
+    pub enabled: bool,
+}
+
+/// Appends every metric to a local file, one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub enabled: bool,
+    pub path: Option<PathBuf>,
+}
+
+impl MqttConfig {
+    /// Whether metrics are published to a broker.
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.broker_url.is_some()
+    }
 }
 
 impl Config {
-    pub fn from_env() -> anyhow::Result<Config> {
-        let broker_url = var("BROKER_URL")
-            .context("Environment variable BROKER_URL is not set or is invalid")?;
-        Ok(Config { broker_url })
+    /// Loads the configuration from `path` (if any) and the process environment.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let content = match path {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Unable to read config file {}", path.display()))?,
+            None => String::new(),
+        };
+        Config::from_toml_str(&content, &env::vars().collect())
+    }
+
+    /// Parses `content` as TOML, then applies the overrides found in `vars`.
+    pub fn from_toml_str(content: &str, vars: &HashMap<String, String>) -> anyhow::Result<Config> {
+        let mut table: toml::Table = toml::from_str(content).context("Invalid TOML")?;
+        apply_env_overrides(&mut table, vars)?;
+        let config: Config = table.try_into().context("Invalid configuration")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values that deserialization alone cannot enforce.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.collection.interval >= Duration::from_secs(1),
+            "collection.interval must be at least 1 second"
+        );
+        ensure!(
+            self.collection.sample_interval < self.collection.interval,
+            "collection.sample_interval must be shorter than collection.interval"
+        );
+        ensure!(
+            !self.collection.categories.is_empty(),
+            "collection.categories must not be empty"
+        );
+        ensure!(
+            !self.mqtt.discovery_prefix.is_empty(),
+            "mqtt.discovery_prefix must not be empty"
+        );
+        if let Some(url) = &self.mqtt.broker_url {
+            ensure!(!url.is_empty(), "mqtt.broker_url must not be empty");
+        }
+        ensure!(
+            self.mqtt.password.is_none() || self.mqtt.password_file.is_none(),
+            "mqtt.password and mqtt.password_file are mutually exclusive"
+        );
+        ensure!(
+            self.mqtt.client_cert.is_some() == self.mqtt.client_key.is_some(),
+            "mqtt.client_cert and mqtt.client_key must be set together"
+        );
+        ensure!(
+            self.mqtt.reconnect_min_delay >= Duration::from_secs(1)
+                && self.mqtt.reconnect_min_delay <= self.mqtt.reconnect_max_delay,
+            "mqtt.reconnect_min_delay must be at least 1 second and at most mqtt.reconnect_max_delay"
+        );
+        ensure!(
+            self.mqtt.heartbeat >= Duration::from_secs(1),
+            "mqtt.heartbeat must be at least 1 second"
+        );
+        for (category, deadband) in &self.mqtt.deadband {
+            ensure!(
+                deadband.is_finite() && *deadband >= 0.0,
+                "mqtt.deadband of {} must be a positive number",
+                category.kind()
+            );
+            ensure!(
+                !category.is_usage() || *deadband <= 100.0,
+                "mqtt.deadband of {} is in percentage points, at most 100",
+                category.kind()
+            );
+        }
+        ensure!(self.processes.top >= 1, "processes.top must be at least 1");
+        ensure!(
+            self.influxdb.batch_size >= 1,
+            "influxdb.batch_size must be at least 1"
+        );
+        ensure!(
+            !self.file.enabled || self.file.path.is_some(),
+            "file.path must be set when file.enabled is true"
+        );
+        let mut watched = HashSet::new();
+        for watch in &self.watch {
+            ensure!(!watch.name.is_empty(), "watch name must not be empty");
+            ensure!(
+                watched.insert(&watch.name),
+                "watch {} is defined twice",
+                watch.name
+            );
+            watch.matcher()?;
+        }
+        let mut names = HashSet::new();
+        for rule in &self.alerts {
+            ensure!(
+                !rule.name.is_empty()
+                    && rule
+                        .name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                "alert name {:?} must only contain letters, digits, _ and -",
+                rule.name
+            );
+            ensure!(
+                names.insert(&rule.name),
+                "alert {} is defined twice",
+                rule.name
+            );
+            ensure!(
+                rule.category.is_usage(),
+                "alert {} category {} has no usage percentage",
+                rule.name,
+                rule.category.kind()
+            );
+            ensure!(
+                rule.warn.is_some() || rule.crit.is_some(),
+                "alert {} needs a warn or crit level",
+                rule.name
+            );
+            ensure!(
+                rule.warn
+                    .into_iter()
+                    .chain(rule.crit)
+                    .all(|level| level <= 100),
+                "alert {} levels must be percentages",
+                rule.name
+            );
+            if let (Some(warn), Some(crit)) = (rule.warn, rule.crit) {
+                ensure!(
+                    warn <= crit,
+                    "alert {} warn level must not exceed its crit level",
+                    rule.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Categories collected on every run: the configured ones, plus the
+    /// watched processes when there are any.
+    pub fn categories(&self) -> Vec<Category> {
+        let mut categories = self.collection.categories.clone();
+        if !self.watch.is_empty() && !categories.contains(&Category::Watch) {
+            categories.push(Category::Watch);
+        }
+        categories
+    }
+
+    /// Renders the effective configuration as TOML, with secrets masked.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let mut masked = self.clone();
+        if masked.mqtt.password.is_some() {
+            masked.mqtt.password = Some("********".to_string());
+        }
+        if masked.influxdb.token.is_some() {
+            masked.influxdb.token = Some("********".to_string());
+        }
+        toml::to_string(&masked).context("Unable to serialize configuration")
+    }
+}
+
+/// Overrides the fields of `table` with the matching `SRVSTAT_*` variables.
+///
+/// Fields are matched against the sections of the default configuration, and
+/// values are converted to the type of the default value when there is one.
+/// Lists of tables are given whole, and maps one entry at a time.
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: &HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let defaults = toml::Table::try_from(Config::default())?;
+    let mut overrides: Vec<(&String, &String)> = vars
+        .iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    overrides.sort();
+
+    if let Some(url) = vars.get(LEGACY_BROKER_URL) {
+        set_field(
+            table,
+            "mqtt",
+            "broker_url",
+            toml::Value::String(url.clone()),
+        )?;
+    }
+
+    for (name, raw) in overrides {
+        let field = name[ENV_PREFIX.len()..].to_lowercase();
+        if let Some(toml::Value::Array(_)) = defaults.get(&field) {
+            table.insert(field, parse_env_array(name, raw)?);
+            continue;
+        }
+        // Longest section name first so that `foo_bar` wins over `foo`
+        let mut sections: Vec<&String> = defaults
+            .iter()
+            .filter(|(_, fields)| fields.is_table())
+            .map(|(section, _)| section)
+            .collect();
+        sections.sort_by_key(|section| std::cmp::Reverse(section.len()));
+        let Some((section, key)) = sections.into_iter().find_map(|section| {
+            field
+                .strip_prefix(section.as_str())
+                .and_then(|rest| rest.strip_prefix('_'))
+                .filter(|key| !key.is_empty())
+                .map(|key| (section.clone(), key.to_string()))
+        }) else {
+            bail!("Unknown configuration variable {}", name);
+        };
+        let fields = &defaults[&section];
+        if let Some(toml::Value::Table(_)) = fields.get(&key) {
+            bail!("{} is a map, set its entries with {}_<KEY>", name, name);
+        }
+        // Map entries, e.g. `deadband_disk` for the `disk` key of `deadband`
+        let map_entry = fields.as_table().and_then(|fields| {
+            fields
+                .iter()
+                .filter(|(_, default)| default.is_table())
+                .find_map(|(map, _)| {
+                    key.strip_prefix(map.as_str())
+                        .and_then(|rest| rest.strip_prefix('_'))
+                        .filter(|entry| !entry.is_empty())
+                        .map(|entry| (map.clone(), entry.to_string()))
+                })
+        });
+        match map_entry {
+            Some((map, entry)) if fields.get(&key).is_none() => {
+                set_map_entry(table, &section, &map, &entry, parse_untyped(raw))?;
+            }
+            _ => {
+                let value = parse_env_value(name, raw, fields.get(&key))?;
+                set_field(table, &section, &key, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_map_entry(
+    table: &mut toml::Table,
+    section: &str,
+    map: &str,
+    entry: &str,
+    value: toml::Value,
+) -> anyhow::Result<()> {
+    let fields = table
+        .entry(section)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let Some(entries) = fields
+        .as_table_mut()
+        .map(|fields| {
+            fields
+                .entry(map)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        })
+        .and_then(toml::Value::as_table_mut)
+    else {
+        bail!("Configuration field {}.{} is not a table", section, map);
+    };
+    entries.insert(entry.to_string(), value);
+    Ok(())
+}
+
+fn set_field(
+    table: &mut toml::Table,
+    section: &str,
+    key: &str,
+    value: toml::Value,
+) -> anyhow::Result<()> {
+    let entry = table
+        .entry(section)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    match entry {
+        toml::Value::Table(fields) => {
+            fields.insert(key.to_string(), value);
+            Ok(())
+        }
+        _ => bail!("Configuration section {} is not a table", section),
+    }
+}
+
+/// Converts an environment variable to the type of the field's default value.
+/// Fields without a default (optional ones) are taken as strings.
+fn parse_env_value(
+    name: &str,
+    raw: &str,
+    default: Option<&toml::Value>,
+) -> anyhow::Result<toml::Value> {
+    let invalid = || format!("Invalid value {:?} for {}", raw, name);
+    let value = match default {
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(raw.trim().parse().with_context(invalid)?)
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(raw.trim().parse().with_context(invalid)?)
+        }
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(raw.trim().parse().with_context(invalid)?)
+        }
+        Some(toml::Value::Array(_)) => toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+        _ => toml::Value::String(raw.to_string()),
+    };
+    Ok(value)
+}
+
+/// Parses a list of tables given as a TOML inline array, e.g.
+/// `[{name = "nginx", process = "nginx"}]`.
+fn parse_env_array(name: &str, raw: &str) -> anyhow::Result<toml::Value> {
+    let mut parsed: toml::Table = toml::from_str(&format!("value = {}", raw))
+        .with_context(|| format!("{} must be a TOML inline array of tables", name))?;
+    match parsed.remove("value") {
+        Some(value @ toml::Value::Array(_)) => Ok(value),
+        _ => bail!("{} must be a TOML inline array of tables", name),
+    }
+}
+
+/// Converts a map entry, whose type is unknown, to a number or a boolean when
+/// it reads as one.
+fn parse_untyped(raw: &str) -> toml::Value {
+    let trimmed = raw.trim();
+    if let Ok(integer) = trimmed.parse() {
+        toml::Value::Integer(integer)
+    } else if let Ok(float) = trimmed.parse() {
+        toml::Value::Float(float)
+    } else if let Ok(boolean) = trimmed.parse() {
+        toml::Value::Boolean(boolean)
+    } else {
+        toml::Value::String(raw.to_string())
+    }
+}
+
+/// (De)serializes a [`Duration`] as a number of whole seconds.
+pub(crate) mod duration_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::from_toml_str("", &HashMap::new()).unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.mqtt.broker_url, None);
+        assert_eq!(config.collection.interval, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let content = r#"
+            [mqtt]
+            broker_url = "tcp://broker:1883"
+            discovery_prefix = "ha"
+
+            [collection]
+            interval = 30
+            categories = ["cpu", "memory", "load", "uptime"]
+        "#;
+        let config = Config::from_toml_str(content, &HashMap::new()).unwrap();
+        assert_eq!(config.mqtt.broker_url.as_deref(), Some("tcp://broker:1883"));
+        assert_eq!(config.mqtt.discovery_prefix, "ha");
+        assert_eq!(config.collection.interval, Duration::from_secs(30));
+        assert_eq!(
+            config.collection.categories,
+            vec![
+                Category::Cpu,
+                Category::Memory,
+                Category::Load,
+                Category::Uptime
+            ]
+        );
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let content = r#"
+            [mqtt]
+            broker_url = "tcp://broker:1883"
+        "#;
+        let env = vars(&[
+            ("SRVSTAT_MQTT_BROKER_URL", "tcp://other:1883"),
+            ("SRVSTAT_COLLECTION_INTERVAL", "15"),
+            ("SRVSTAT_COLLECTION_CATEGORIES", "disk, swap"),
+            ("PATH", "/usr/bin"),
+        ]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        assert_eq!(config.mqtt.broker_url.as_deref(), Some("tcp://other:1883"));
+        assert_eq!(config.collection.interval, Duration::from_secs(15));
+        assert_eq!(
+            config.collection.categories,
+            vec![Category::Disk, Category::Swap]
+        );
+    }
+
+    #[test]
+    fn test_legacy_broker_url() {
+        let env = vars(&[("BROKER_URL", "tcp://legacy:1883")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.mqtt.broker_url.as_deref(), Some("tcp://legacy:1883"));
+
+        let env = vars(&[
+            ("BROKER_URL", "tcp://legacy:1883"),
+            ("SRVSTAT_MQTT_BROKER_URL", "tcp://new:1883"),
+        ]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.mqtt.broker_url.as_deref(), Some("tcp://new:1883"));
+    }
+
+    #[test]
+    fn test_invalid_env_override() {
+        let env = vars(&[("SRVSTAT_COLLECTION_INTERVAL", "soon")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+
+        let env = vars(&[("SRVSTAT_COLLECTION_UNKNOWN", "1")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+
+        let env = vars(&[("SRVSTAT_NOPE", "1")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+
+        let env = vars(&[("SRVSTAT_MQTT_DEADBAND", "disk=2")]);
+        let error = Config::from_toml_str("", &env).unwrap_err();
+        assert!(error.to_string().contains("SRVSTAT_MQTT_DEADBAND_<KEY>"));
+
+        let env = vars(&[("SRVSTAT_ALERTS", "disk_full")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+    }
+
+    #[test]
+    fn test_env_map_entries() {
+        let content = "[mqtt]\ndeadband = { disk = 1, memory = 2 }";
+        let env = vars(&[
+            ("SRVSTAT_MQTT_DEADBAND_DISK", "2"),
+            ("SRVSTAT_MQTT_DEADBAND_TEMPERATURE", "0.5"),
+        ]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        assert_eq!(
+            config.mqtt.deadband,
+            BTreeMap::from([
+                (Category::Disk, 2.0),
+                (Category::Memory, 2.0),
+                (Category::Temperature, 0.5)
+            ])
+        );
+
+        let env = vars(&[("SRVSTAT_MQTT_DEADBAND_GPU", "1")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+    }
+
+    #[test]
+    fn test_env_lists_of_tables() {
+        let content = "[[watch]]\nname = \"sshd\"\nprocess = \"sshd\"";
+        let env = vars(&[
+            (
+                "SRVSTAT_WATCH",
+                r#"[{name = "nginx", process = "nginx"}, {name = "app", cmdline = "java .*"}]"#,
+            ),
+            (
+                "SRVSTAT_ALERTS",
+                r#"[{name = "disk_full", category = "disk", warn = 80}]"#,
+            ),
+        ]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        let names: Vec<&str> = config.watch.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, vec!["nginx", "app"]);
+        assert_eq!(config.alerts.len(), 1);
+        assert_eq!(config.alerts[0].name, "disk_full");
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let empty = HashMap::new();
+        assert!(Config::from_toml_str("[mqtt]\nbroker = \"x\"", &empty).is_err());
+        assert!(Config::from_toml_str("[collection]\ninterval = 0", &empty).is_err());
+        assert!(Config::from_toml_str("[collection]\ncategories = []", &empty).is_err());
+        assert!(Config::from_toml_str("[collection]\ncategories = [\"gpu\"]", &empty).is_err());
+        assert!(Config::from_toml_str("not toml", &empty).is_err());
+    }
+
+    #[test]
+    fn test_mqtt_credentials() {
+        let content = r#"
+            [mqtt]
+            broker_url = "ssl://broker:8883"
+            client_id = "srvstat-nas"
+            keep_alive = 30
+            username = "srvstat"
+            password_file = "/run/secrets/mqtt"
+            ca_file = "/etc/ssl/ca.pem"
+            client_cert = "/etc/ssl/client.pem"
+            client_key = "/etc/ssl/client.key"
+        "#;
+        let env = vars(&[("SRVSTAT_MQTT_USERNAME", "other")]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        assert_eq!(config.mqtt.client_id.as_deref(), Some("srvstat-nas"));
+        assert_eq!(config.mqtt.keep_alive, Duration::from_secs(30));
+        assert_eq!(config.mqtt.username.as_deref(), Some("other"));
+        assert_eq!(
+            config.mqtt.password_file,
+            Some(PathBuf::from("/run/secrets/mqtt"))
+        );
+        assert_eq!(config.mqtt.ca_file, Some(PathBuf::from("/etc/ssl/ca.pem")));
+    }
+
+    #[test]
+    fn test_mqtt_reconnect_and_queue() {
+        let content = r#"
+            [mqtt]
+            reconnect_min_delay = 2
+            reconnect_max_delay = 120
+            queue_size = 50
+            queue_file = "/var/lib/srvstat/queue.jsonl"
+        "#;
+        let env = vars(&[("SRVSTAT_MQTT_QUEUE_SIZE", "10")]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        assert_eq!(config.mqtt.reconnect_min_delay, Duration::from_secs(2));
+        assert_eq!(config.mqtt.reconnect_max_delay, Duration::from_secs(120));
+        assert_eq!(config.mqtt.queue_size, 10);
+        assert_eq!(
+            config.mqtt.queue_file,
+            Some(PathBuf::from("/var/lib/srvstat/queue.jsonl"))
+        );
+
+        let empty = HashMap::new();
+        let inverted = "[mqtt]\nreconnect_min_delay = 10\nreconnect_max_delay = 5";
+        assert!(Config::from_toml_str(inverted, &empty).is_err());
+    }
+
+    #[test]
+    fn test_mqtt_deadband_and_heartbeat() {
+        let content = r#"
+            [mqtt]
+            heartbeat = 120
+            deadband = { disk = 1, memory = 2 }
+        "#;
+        let env = vars(&[("SRVSTAT_MQTT_HEARTBEAT", "60")]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+        assert_eq!(config.mqtt.heartbeat, Duration::from_secs(60));
+        assert_eq!(
+            config.mqtt.deadband,
+            BTreeMap::from([(Category::Disk, 1.0), (Category::Memory, 2.0)])
+        );
+        let rendered = Config::from_toml_str(&config.to_toml().unwrap(), &HashMap::new()).unwrap();
+        assert_eq!(rendered, config);
+
+        let empty = HashMap::new();
+        assert!(Config::from_toml_str("[mqtt]\nheartbeat = 0", &empty).is_err());
+        assert!(Config::from_toml_str("[mqtt]\ndeadband = { gpu = 1 }", &empty).is_err());
+        assert!(Config::from_toml_str("[mqtt]\ndeadband = { disk = 101 }", &empty).is_err());
+        assert!(Config::from_toml_str("[mqtt]\ndeadband = { disk = -1 }", &empty).is_err());
+        // Rates and temperatures have their own unit
+        let native = "[mqtt]\ndeadband = { network = 1024, temperature = 0.5 }";
+        let config = Config::from_toml_str(native, &empty).unwrap();
+        assert_eq!(config.mqtt.deadband[&Category::Temperature], 0.5);
+        assert_eq!(config.mqtt.deadband[&Category::Network], 1024.0);
+    }
+
+    #[test]
+    fn test_long_interval_with_broker() {
+        // The sensors expire later instead of the interval being limited
+        let env = vars(&[
+            ("SRVSTAT_MQTT_BROKER_URL", "tcp://localhost:1883"),
+            ("SRVSTAT_COLLECTION_INTERVAL", "300"),
+        ]);
+        let config = Config::from_toml_str("[mqtt]\nheartbeat = 600", &env).unwrap();
+        assert_eq!(config.collection.interval, Duration::from_secs(300));
+        assert_eq!(config.mqtt.heartbeat, Duration::from_secs(600));
+    }
+
+    #[test]
+    fn test_sample_interval() {
+        assert!(Config::default().collection.sample_interval.is_zero());
+        let env = vars(&[("SRVSTAT_COLLECTION_SAMPLE_INTERVAL", "5")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.collection.sample_interval, Duration::from_secs(5));
+
+        let content = "[collection]\ninterval = 10\nsample_interval = 10";
+        assert!(Config::from_toml_str(content, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_sysfs_root() {
+        assert_eq!(Config::default().collection.sysfs_root, Path::new("/sys"));
+        let env = vars(&[("SRVSTAT_COLLECTION_SYSFS_ROOT", "/host/sys")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.collection.sysfs_root, Path::new("/host/sys"));
+    }
+
+    #[test]
+    fn test_procfs_root() {
+        assert_eq!(Config::default().collection.procfs_root, Path::new("/proc"));
+        let env = vars(&[("SRVSTAT_COLLECTION_PROCFS_ROOT", "/host/proc")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.collection.procfs_root, Path::new("/host/proc"));
+    }
+
+    #[test]
+    fn test_cpu_per_core() {
+        assert!(Config::default().cpu.per_core);
+        let env = vars(&[("SRVSTAT_CPU_PER_CORE", "false")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert!(!config.cpu.per_core);
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let env = vars(&[
+            ("SRVSTAT_PROMETHEUS_ENABLED", "true"),
+            ("SRVSTAT_PROMETHEUS_LISTEN", "127.0.0.1:9100"),
+        ]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert!(config.prometheus.enabled);
+        assert_eq!(config.prometheus.listen, "127.0.0.1:9100");
+    }
+
+    #[test]
+    fn test_influxdb() {
+        let toml =
+            "[influxdb]\nenabled = true\nurl = \"udp://localhost:8089\"\ntoken = \"secret\"\n";
+        let config = Config::from_toml_str(toml, &HashMap::new()).unwrap();
+        assert!(config.influxdb.enabled);
+        assert_eq!(config.influxdb.url, "udp://localhost:8089");
+        assert_eq!(config.influxdb.batch_size, 100);
+        assert!(!config.to_toml().unwrap().contains("secret"));
+
+        let env = vars(&[("SRVSTAT_INFLUXDB_BATCH_SIZE", "0")]);
+        assert!(Config::from_toml_str("", &env).is_err());
+    }
+
+    #[test]
+    fn test_backends() {
+        let toml = r#"
+            [mqtt]
+            broker_url = "tcp://localhost:1883"
+
+            [prometheus]
+            enabled = true
+
+            [console]
+            enabled = true
+
+            [file]
+            enabled = true
+            path = "/var/lib/srvstat/metrics.jsonl"
+        "#;
+        let config = Config::from_toml_str(toml, &HashMap::new()).unwrap();
+        assert!(config.mqtt.is_active());
+        assert!(config.prometheus.enabled);
+        assert!(config.console.enabled);
+        assert!(config.file.enabled);
+
+        let env = vars(&[("SRVSTAT_MQTT_ENABLED", "false")]);
+        let config = Config::from_toml_str(toml, &env).unwrap();
+        assert!(!config.mqtt.is_active());
+
+        let toml = "[file]\nenabled = true\n";
+        assert!(Config::from_toml_str(toml, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_alerts() {
+        let toml = r#"
+            [[alerts]]
+            name = "disk_full"
+            category = "disk"
+            warn = 80
+            crit = 90
+            duration = 300
+            hysteresis = 5
+
+            [[alerts]]
+            name = "swap_used"
+            category = "swap"
+            warn = 50
+        "#;
+        let config = Config::from_toml_str(toml, &HashMap::new()).unwrap();
+        assert_eq!(config.alerts.len(), 2);
+        assert_eq!(config.alerts[0].category, Category::Disk);
+        assert_eq!(config.alerts[0].duration, Duration::from_secs(300));
+        assert_eq!(config.alerts[1].crit, None);
+        assert_eq!(config.alerts[1].duration, Duration::ZERO);
+
+        let rendered = config.to_toml().unwrap();
+        assert_eq!(
+            Config::from_toml_str(&rendered, &HashMap::new()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_invalid_alerts() {
+        for toml in [
+            "[[alerts]]\nname = \"a\"\ncategory = \"disk\"\n",
+            "[[alerts]]\nname = \"a b\"\ncategory = \"disk\"\nwarn = 1\n",
+            "[[alerts]]\nname = \"a\"\ncategory = \"disk\"\nwarn = 90\ncrit = 80\n",
+            "[[alerts]]\nname = \"a\"\ncategory = \"disk\"\ncrit = 101\n",
+            "[[alerts]]\nname = \"a\"\ncategory = \"load\"\nwarn = 1\n",
+            "[[alerts]]\nname = \"a\"\ncategory = \"disk\"\nwarn = 1\n[[alerts]]\nname = \"a\"\ncategory = \"swap\"\nwarn = 1\n",
+        ] {
+            assert!(Config::from_toml_str(toml, &HashMap::new()).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn test_disk_filters() {
+        let content = r#"
+            [disk]
+            include_mounts = ["/", "/mnt/*"]
+            exclude_mounts = ["/mnt/backup"]
+        "#;
+        let env = vars(&[("SRVSTAT_DISK_EXCLUDE_FS_TYPES", "tmpfs,nfs")]);
+        let config = Config::from_toml_str(content, &env).unwrap();
+
+        let mounts = config.disk.mount_filter();
+        assert!(mounts.allows("/"));
+        assert!(mounts.allows("/mnt/data"));
+        assert!(!mounts.allows("/mnt/backup"));
+        assert!(!mounts.allows("/home"));
+
+        let fs_types = config.disk.fs_type_filter();
+        assert!(fs_types.allows("ext4"));
+        assert!(fs_types.allows("overlay"));
+        assert!(!fs_types.allows("nfs"));
+    }
+
+    #[test]
+    fn test_default_disk_filters() {
+        let fs_types = DiskConfig::default().fs_type_filter();
+        assert!(fs_types.allows("ext4"));
+        for fs_type in ["tmpfs", "overlay", "squashfs"] {
+            assert!(!fs_types.allows(fs_type));
+        }
+    }
+
+    #[test]
+    fn test_network_filters() {
+        let interfaces = NetworkConfig::default().interface_filter();
+        assert!(interfaces.allows("eth0"));
+        for name in ["lo", "veth1a2b", "docker0"] {
+            assert!(!interfaces.allows(name));
+        }
+
+        let env = vars(&[("SRVSTAT_NETWORK_INCLUDE_INTERFACES", "eth*,wlan0")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        let interfaces = config.network.interface_filter();
+        assert!(interfaces.allows("eth1"));
+        assert!(interfaces.allows("wlan0"));
+        assert!(!interfaces.allows("wlan1"));
+    }
+
+    #[test]
+    fn test_processes_top() {
+        assert_eq!(Config::default().processes.top, 5);
+        let env = vars(&[("SRVSTAT_PROCESSES_TOP", "10")]);
+        let config = Config::from_toml_str("", &env).unwrap();
+        assert_eq!(config.processes.top, 10);
+        assert!(Config::from_toml_str("[processes]\ntop = 0", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_cgroups() {
+        assert_eq!(Config::default().cgroups.root, Path::new("/sys/fs/cgroup"));
+        let content = r#"
+            [cgroups]
+            root = "/host/cgroup"
+            include_cgroups = ["system.slice/*"]
+            exclude_cgroups = ["system.slice/systemd-*"]
+        "#;
+        let config = Config::from_toml_str(content, &HashMap::new()).unwrap();
+        assert_eq!(config.cgroups.root, Path::new("/host/cgroup"));
+        let cgroups = config.cgroups.cgroup_filter();
+        assert!(cgroups.allows("system.slice/nginx.service"));
+        assert!(!cgroups.allows("system.slice/systemd-journald.service"));
+        assert!(!cgroups.allows("user.slice"));
+    }
+
+    #[test]
+    fn test_watch() {
+        let content = r#"
+            [[watch]]
+            name = "nginx"
+            process = "nginx"
+
+            [[watch]]
+            name = "app"
+            cmdline = "java .*app\\.jar"
+        "#;
+        let config = Config::from_toml_str(content, &HashMap::new()).unwrap();
+        assert_eq!(config.watch.len(), 2);
+        assert!(matches!(
+            config.watch[1].matcher().unwrap(),
+            ProcessMatcher::Cmdline(_)
+        ));
+        assert_eq!(
+            config.categories(),
+            vec![
+                Category::Disk,
+                Category::Memory,
+                Category::Cpu,
+                Category::Swap,
+                Category::Watch
+            ]
+        );
+        assert_eq!(
+            Config::default().categories(),
+            Config::default().collection.categories
+        );
+    }
+
+    #[test]
+    fn test_invalid_watch() {
+        for toml in [
+            "[[watch]]\nname = \"a\"\n",
+            "[[watch]]\nname = \"a\"\nprocess = \"a\"\nexe = \"/bin/a\"\n",
+            "[[watch]]\nname = \"a\"\ncmdline = \"(\"\n",
+            "[[watch]]\nname = \"\"\nprocess = \"a\"\n",
+            "[[watch]]\nname = \"a\"\nprocess = \"a\"\n[[watch]]\nname = \"a\"\nprocess = \"b\"\n",
+        ] {
+            assert!(
+                Config::from_toml_str(toml, &HashMap::new()).is_err(),
+                "{}",
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_mqtt_credentials() {
+        let empty = HashMap::new();
+        let both_passwords = "[mqtt]\npassword = \"x\"\npassword_file = \"/tmp/x\"";
+        assert!(Config::from_toml_str(both_passwords, &empty).is_err());
+        let cert_without_key = "[mqtt]\nclient_cert = \"/tmp/cert.pem\"";
+        assert!(Config::from_toml_str(cert_without_key, &empty).is_err());
+    }
+
+    #[test]
+    fn test_to_toml_masks_password() {
+        let mut config = Config::default();
+        config.mqtt.password = Some("secret".to_string());
+        let rendered = config.to_toml().unwrap();
+        assert!(!rendered.contains("secret"));
+        assert!(rendered.contains("password = \"********\""));
+    }
+
+    #[test]
+    fn test_to_toml_round_trip() {
+        let mut config = Config::default();
+        config.mqtt.broker_url = Some("tcp://broker:1883".to_string());
+        let rendered = config.to_toml().unwrap();
+        let parsed = Config::from_toml_str(&rendered, &HashMap::new()).unwrap();
+        assert_eq!(parsed, config);
     }
 }
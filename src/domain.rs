@@ -1,3 +1,6 @@
+pub mod alerts;
+pub mod filter;
 pub mod ha;
 pub mod metrics;
 pub mod ports;
+pub mod watch;
@@ -0,0 +1,2 @@
+pub mod alert_engine;
+pub mod models;
@@ -0,0 +1,252 @@
+use crate::domain::alerts::models::{AlertLevel, AlertRule, AlertTransition};
+use crate::domain::metrics::models::{Category, Metric, Percentage};
+use crate::domain::ports::Snapshot;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// Level of a rule for one series, and since when each threshold has been
+/// exceeded, a higher level waiting for the rule duration to elapse.
+#[derive(Debug)]
+struct SeriesState {
+    level: AlertLevel,
+    warn_since: Option<SystemTime>,
+    crit_since: Option<SystemTime>,
+}
+
+/// Evaluates the alert rules against every collection and reports the level
+/// changes.
+#[derive(Debug, Default)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    // Keyed by rule index, host and category
+    states: HashMap<(usize, String, Category), SeriesState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        AlertEngine {
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Feeds the usage percentages of a collection to the rules.
+    pub fn evaluate(&mut self, snapshot: &Snapshot) -> Vec<AlertTransition> {
+        let mut transitions = Vec::new();
+        for metric in &snapshot.metrics {
+            let Metric::Percent(host, category, Percentage(value)) = metric else {
+                continue;
+            };
+            for (index, rule) in self.rules.iter().enumerate() {
+                if !rule.applies_to(category) {
+                    continue;
+                }
+                let key = (index, host.clone(), category.clone());
+                let transition = match self.states.get_mut(&key) {
+                    Some(state) => update(state, rule, *value, snapshot.timestamp)
+                        .map(|previous| (Some(previous), state.level)),
+                    None => {
+                        let mut state = SeriesState {
+                            level: AlertLevel::Ok,
+                            warn_since: None,
+                            crit_since: None,
+                        };
+                        update(&mut state, rule, *value, snapshot.timestamp);
+                        let level = state.level;
+                        self.states.insert(key, state);
+                        Some((None, level))
+                    }
+                };
+                if let Some((previous, level)) = transition {
+                    transitions.push(AlertTransition {
+                        rule: rule.name.clone(),
+                        host: host.clone(),
+                        category: category.clone(),
+                        previous,
+                        level,
+                        value: *value,
+                        timestamp: snapshot.timestamp,
+                    });
+                }
+            }
+        }
+        transitions
+    }
+}
+
+// Apply a reading to a series, returning the previous level when it changed.
+// Each threshold has its own timer, so moving between WARN and CRIT does not
+// delay the WARN
+fn update(
+    state: &mut SeriesState,
+    rule: &AlertRule,
+    value: u8,
+    now: SystemTime,
+) -> Option<AlertLevel> {
+    let current = state.level;
+    let since = |level: AlertLevel, since: &mut Option<SystemTime>| {
+        if rule.reached(level, value, current) {
+            Some(*since.get_or_insert(now))
+        } else {
+            *since = None;
+            None
+        }
+    };
+    let crit_since = since(AlertLevel::Crit, &mut state.crit_since);
+    let warn_since = since(AlertLevel::Warn, &mut state.warn_since);
+    // Levels up to the current one are kept without waiting again
+    let raised = |level: AlertLevel, since: Option<SystemTime>| {
+        since.is_some_and(|since| {
+            level <= current || now.duration_since(since).unwrap_or_default() >= rule.duration
+        })
+    };
+    let target = if raised(AlertLevel::Crit, crit_since) {
+        AlertLevel::Crit
+    } else if raised(AlertLevel::Warn, warn_since) {
+        AlertLevel::Warn
+    } else {
+        AlertLevel::Ok
+    };
+    if target == current {
+        return None;
+    }
+    state.level = target;
+    Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn engine(duration: u64) -> AlertEngine {
+        AlertEngine::new(vec![AlertRule {
+            name: "disk_full".to_string(),
+            category: Category::Disk,
+            warn: Some(80),
+            crit: Some(90),
+            duration: Duration::from_secs(duration),
+            hysteresis: 5,
+        }])
+    }
+
+    fn snapshot(seconds: u64, values: &[(Category, u8)]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        for (category, value) in values {
+            snapshot.push(Ok(Metric::Percent(
+                "tux".to_string(),
+                category.clone(),
+                Percentage(*value),
+            )));
+            snapshot.push(Ok(Metric::Used("tux".to_string(), category.clone(), 1, 2)));
+        }
+        snapshot
+    }
+
+    fn levels(transitions: &[AlertTransition]) -> Vec<(Option<AlertLevel>, AlertLevel)> {
+        transitions.iter().map(|t| (t.previous, t.level)).collect()
+    }
+
+    #[test]
+    fn test_first_evaluation_reports_level() {
+        let mut engine = engine(0);
+        let transitions = engine.evaluate(&snapshot(
+            0,
+            &[(Category::Disk, 95), (Category::Memory, 95)],
+        ));
+        assert_eq!(levels(&transitions), vec![(None, AlertLevel::Crit)]);
+        assert!(engine
+            .evaluate(&snapshot(60, &[(Category::Disk, 95)]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_duration_delays_raising() {
+        let mut engine = engine(300);
+        let transitions = engine.evaluate(&snapshot(0, &[(Category::Disk, 95)]));
+        assert_eq!(levels(&transitions), vec![(None, AlertLevel::Ok)]);
+        assert!(engine
+            .evaluate(&snapshot(120, &[(Category::Disk, 95)]))
+            .is_empty());
+        let transitions = engine.evaluate(&snapshot(300, &[(Category::Disk, 95)]));
+        assert_eq!(
+            levels(&transitions),
+            vec![(Some(AlertLevel::Ok), AlertLevel::Crit)]
+        );
+    }
+
+    #[test]
+    fn test_dip_resets_duration() {
+        let mut engine = engine(300);
+        engine.evaluate(&snapshot(0, &[(Category::Disk, 95)]));
+        engine.evaluate(&snapshot(200, &[(Category::Disk, 50)]));
+        assert!(engine
+            .evaluate(&snapshot(400, &[(Category::Disk, 95)]))
+            .is_empty());
+        assert_eq!(
+            levels(&engine.evaluate(&snapshot(700, &[(Category::Disk, 95)]))),
+            vec![(Some(AlertLevel::Ok), AlertLevel::Crit)]
+        );
+    }
+
+    #[test]
+    fn test_warn_duration_spans_crit() {
+        let mut engine = engine(300);
+        engine.evaluate(&snapshot(0, &[(Category::Disk, 85)]));
+        assert!(engine
+            .evaluate(&snapshot(120, &[(Category::Disk, 95)]))
+            .is_empty());
+        assert!(engine
+            .evaluate(&snapshot(240, &[(Category::Disk, 85)]))
+            .is_empty());
+        assert_eq!(
+            levels(&engine.evaluate(&snapshot(300, &[(Category::Disk, 95)]))),
+            vec![(Some(AlertLevel::Ok), AlertLevel::Warn)]
+        );
+        assert!(engine
+            .evaluate(&snapshot(360, &[(Category::Disk, 85)]))
+            .is_empty());
+        // The CRIT timer restarted when the usage fell below its threshold
+        assert!(engine
+            .evaluate(&snapshot(420, &[(Category::Disk, 95)]))
+            .is_empty());
+        assert_eq!(
+            levels(&engine.evaluate(&snapshot(720, &[(Category::Disk, 95)]))),
+            vec![(Some(AlertLevel::Warn), AlertLevel::Crit)]
+        );
+    }
+
+    #[test]
+    fn test_recovery_hysteresis() {
+        let mut engine = engine(0);
+        engine.evaluate(&snapshot(0, &[(Category::Disk, 92)]));
+        assert!(engine
+            .evaluate(&snapshot(60, &[(Category::Disk, 87)]))
+            .is_empty());
+        assert_eq!(
+            levels(&engine.evaluate(&snapshot(120, &[(Category::Disk, 84)]))),
+            vec![(Some(AlertLevel::Crit), AlertLevel::Warn)]
+        );
+        assert_eq!(
+            levels(&engine.evaluate(&snapshot(180, &[(Category::Disk, 10)]))),
+            vec![(Some(AlertLevel::Warn), AlertLevel::Ok)]
+        );
+    }
+
+    #[test]
+    fn test_filesystems_are_tracked_separately() {
+        let mut engine = engine(0);
+        let home = Category::Filesystem("/home".to_string());
+        let root = Category::Filesystem("/".to_string());
+        engine.evaluate(&snapshot(0, &[(home.clone(), 50), (root.clone(), 50)]));
+        let transitions = engine.evaluate(&snapshot(60, &[(home.clone(), 85), (root, 50)]));
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].category, home);
+        assert_eq!(transitions[0].level, AlertLevel::Warn);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::domain::metrics::models::Category;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// Severity of an alert, ordered from OK to CRIT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AlertLevel {
+    Ok,
+    Warn,
+    Crit,
+}
+
+impl fmt::Display for AlertLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertLevel::Ok => write!(f, "OK"),
+            AlertLevel::Warn => write!(f, "WARN"),
+            AlertLevel::Crit => write!(f, "CRIT"),
+        }
+    }
+}
+
+/// A threshold rule on the usage percentage of a category.
+///
+/// The rule applies to the category and to each of its filesystems or cores.
+/// A level is raised once the usage stayed above its threshold for
+/// `duration`, and lowered as soon as the usage falls `hysteresis` points
+/// below it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    /// Identifies the rule in topics and logs, e.g. `disk_full`.
+    pub name: String,
+    pub category: Category,
+    /// Usage percentage raising a WARN.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warn: Option<u8>,
+    /// Usage percentage raising a CRIT.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crit: Option<u8>,
+    /// Time a threshold must be exceeded before the level is raised, in seconds.
+    #[serde(default, with = "crate::config::duration_secs")]
+    pub duration: Duration,
+    /// Percentage points below a threshold needed to lower the level.
+    #[serde(default)]
+    pub hysteresis: u8,
+}
+
+impl AlertRule {
+    pub fn applies_to(&self, category: &Category) -> bool {
+        category.selector() == self.category
+    }
+
+    /// Level matching `value`, staying at `current` within the hysteresis band.
+    pub fn level(&self, value: u8, current: AlertLevel) -> AlertLevel {
+        if self.reached(AlertLevel::Crit, value, current) {
+            AlertLevel::Crit
+        } else if self.reached(AlertLevel::Warn, value, current) {
+            AlertLevel::Warn
+        } else {
+            AlertLevel::Ok
+        }
+    }
+
+    /// Whether `value` is above the threshold of `level`, lowered by the
+    /// hysteresis when `current` is already at that level or higher.
+    pub fn reached(&self, level: AlertLevel, value: u8, current: AlertLevel) -> bool {
+        let threshold = match level {
+            AlertLevel::Ok => return true,
+            AlertLevel::Warn => self.warn,
+            AlertLevel::Crit => self.crit,
+        };
+        threshold.is_some_and(|threshold| {
+            let margin = if current >= level { self.hysteresis } else { 0 };
+            value >= threshold.saturating_sub(margin)
+        })
+    }
+}
+
+/// Change of level of a rule for one host and category. The first evaluation
+/// of a series has no previous level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertTransition {
+    pub rule: String,
+    pub host: String,
+    pub category: Category,
+    pub previous: Option<AlertLevel>,
+    pub level: AlertLevel,
+    pub value: u8,
+    pub timestamp: SystemTime,
+}
+
+impl fmt::Display for AlertTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.previous {
+            Some(previous) => write!(
+                f,
+                "{}-{} {}: {} -> {} ({}%)",
+                self.host, self.category, self.rule, previous, self.level, self.value
+            ),
+            None => write!(
+                f,
+                "{}-{} {}: {} ({}%)",
+                self.host, self.category, self.rule, self.level, self.value
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> AlertRule {
+        AlertRule {
+            name: "disk_full".to_string(),
+            category: Category::Disk,
+            warn: Some(80),
+            crit: Some(90),
+            duration: Duration::ZERO,
+            hysteresis: 5,
+        }
+    }
+
+    #[test]
+    fn test_level() {
+        let rule = rule();
+        assert_eq!(rule.level(50, AlertLevel::Ok), AlertLevel::Ok);
+        assert_eq!(rule.level(80, AlertLevel::Ok), AlertLevel::Warn);
+        assert_eq!(rule.level(95, AlertLevel::Ok), AlertLevel::Crit);
+    }
+
+    #[test]
+    fn test_level_hysteresis() {
+        let rule = rule();
+        assert_eq!(rule.level(86, AlertLevel::Crit), AlertLevel::Crit);
+        assert_eq!(rule.level(84, AlertLevel::Crit), AlertLevel::Warn);
+        assert_eq!(rule.level(76, AlertLevel::Warn), AlertLevel::Warn);
+        assert_eq!(rule.level(74, AlertLevel::Warn), AlertLevel::Ok);
+        assert_eq!(rule.level(79, AlertLevel::Ok), AlertLevel::Ok);
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let rule = rule();
+        assert!(rule.applies_to(&Category::Disk));
+        assert!(rule.applies_to(&Category::Filesystem("/home".to_string())));
+        assert!(!rule.applies_to(&Category::Memory));
+    }
+
+    #[test]
+    fn test_transition_display() {
+        let transition = AlertTransition {
+            rule: "disk_full".to_string(),
+            host: "tux".to_string(),
+            category: Category::Filesystem("/home".to_string()),
+            previous: Some(AlertLevel::Ok),
+            level: AlertLevel::Warn,
+            value: 85,
+            timestamp: SystemTime::UNIX_EPOCH,
+        };
+        assert_eq!(
+            transition.to_string(),
+            "tux-Filesystem /home disk_full: OK -> WARN (85%)"
+        );
+    }
+}
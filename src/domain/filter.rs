@@ -0,0 +1,69 @@
+/// Include/exclude lists of patterns selecting what gets reported.
+///
+/// A pattern matches a value exactly, or by prefix when it ends with `*`
+/// (`veth*`). An empty include list includes everything; exclusions win over
+/// inclusions.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Filter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Filter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Filter { include, exclude }
+    }
+
+    pub fn allows(&self, value: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|p| matches(p, value));
+        included && !self.exclude.iter().any(|p| matches(p, value))
+    }
+}
+
+fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_empty_filter_allows_everything() {
+        let filter = Filter::default();
+        assert!(filter.allows("/"));
+        assert!(filter.allows("eth0"));
+    }
+
+    #[test]
+    fn test_include() {
+        let filter = Filter::new(strings(&["/", "/mnt/*"]), vec![]);
+        assert!(filter.allows("/"));
+        assert!(filter.allows("/mnt/data"));
+        assert!(!filter.allows("/home"));
+    }
+
+    #[test]
+    fn test_exclude_wins() {
+        let filter = Filter::new(strings(&["/mnt/*"]), strings(&["/mnt/backup"]));
+        assert!(filter.allows("/mnt/data"));
+        assert!(!filter.allows("/mnt/backup"));
+    }
+
+    #[test]
+    fn test_prefix_pattern() {
+        let filter = Filter::new(vec![], strings(&["lo", "veth*", "docker*"]));
+        assert!(!filter.allows("lo"));
+        assert!(!filter.allows("veth1a2b"));
+        assert!(!filter.allows("docker0"));
+        assert!(filter.allows("eth0"));
+        assert!(filter.allows("lo0"));
+    }
+}
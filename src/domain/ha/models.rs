@@ -1,21 +1,181 @@
-use crate::domain::metrics::models::{Category, Metric};
+use crate::domain::alerts::models::AlertTransition;
+use crate::domain::metrics::models::{mount_slug, readable, Category, Metric, Quantity};
+use crate::domain::ports::MetricError;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+/// Kind of Home Assistant entity, which selects its discovery topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Component {
+    #[default]
+    Sensor,
+    /// On/off entity, e.g. a problem raised by an alert rule.
+    BinarySensor,
+}
+
+impl Component {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+        }
+    }
+}
+
+/// Discovery config of a Home Assistant entity. Sensor-only fields (unit,
+/// state class, expiry) and binary sensor ones (device class, on/off
+/// payloads) are left out of the payload when unset.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct HomeAssistantDiscoveryConfig {
+    #[serde(skip)]
+    component: Component,
+    #[serde(skip, default = "default_discovery_prefix")]
+    discovery_prefix: String,
     name: String,
     unique_id: String,
     state_topic: String,
-    unit_of_measurement: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_of_measurement: Option<String>,
     value_template: String,
-    state_class: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_class: Option<String>,
     icon: String,
-    expire_after: i16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expire_after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_on: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_off: Option<String>,
+    availability_topic: String,
+    payload_available: String,
+    payload_not_available: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_attributes_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_attributes_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<HomeAssistantDevice>,
+}
+
+/// Device grouping every sensor of a host on one Home Assistant device page.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HomeAssistantDevice {
+    identifiers: Vec<String>,
+    name: String,
+    model: String,
+    manufacturer: String,
+    sw_version: String,
+}
+
+impl HomeAssistantDevice {
+    /// Creates the device of a host, identified by `id` (e.g. its machine-id)
+    /// and described by `model` (e.g. its OS and kernel).
+    pub fn new(id: &str, host: &str, model: String) -> Self {
+        HomeAssistantDevice {
+            identifiers: vec![format!("srvstat_{}", id)],
+            name: host.to_string(),
+            model,
+            manufacturer: "srvstat".to_string(),
+            sw_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Default Home Assistant discovery prefix.
+pub const DISCOVERY_PREFIX: &str = "homeassistant";
+/// Availability payload published once connected.
+pub const PAYLOAD_ONLINE: &str = "online";
+/// Availability payload published on shutdown, and by the broker (Last Will) when the host dies.
+pub const PAYLOAD_OFFLINE: &str = "offline";
+
+/// Binary sensor state of an alert rule at WARN or CRIT.
+pub const PAYLOAD_PROBLEM: &str = "ON";
+/// Binary sensor state of an alert rule at OK.
+pub const PAYLOAD_NO_PROBLEM: &str = "OFF";
+/// Binary sensor state of a watched process with running instances.
+pub const PAYLOAD_RUNNING: &str = "ON";
+/// Binary sensor state of a watched process without any instance.
+pub const PAYLOAD_NOT_RUNNING: &str = "OFF";
+
+/// Seconds after which Home Assistant marks a sensor without update as
+/// unavailable, unless the writer sets its own.
+pub const EXPIRE_AFTER: u64 = 300;
+
+fn default_discovery_prefix() -> String {
+    DISCOVERY_PREFIX.to_string()
+}
+
+/// Topic holding the availability (`online`/`offline`) of every sensor of a host.
+pub fn availability_topic(host: &str) -> String {
+    format!("srvstat/{}/availability", host.to_lowercase())
+}
+
+/// Topic holding the level (`OK`/`WARN`/`CRIT`) of an alert rule for a category.
+pub fn alert_topic(host: &str, rule: &str, category: &Category) -> String {
+    format!(
+        "srvstat/{}/alerts/{}/{}",
+        host.to_lowercase(),
+        rule,
+        category.slug()
+    )
+}
+
+/// Topic holding the processes using the most of a resource (`Cpu` or
+/// `Memory`), which are also the attributes of its usage sensor.
+pub fn processes_topic(host: &str, category: &Category) -> String {
+    format!(
+        "srvstat/{}/processes/{}",
+        host.to_lowercase(),
+        category.slug()
+    )
 }
 
 impl HomeAssistantDiscoveryConfig {
+    /// Moves the sensor topics under another discovery prefix.
+    pub fn with_discovery_prefix(mut self, prefix: &str) -> Self {
+        self.discovery_prefix = prefix.to_string();
+        if let Some(topic) = self.state_topic.strip_prefix(DISCOVERY_PREFIX) {
+            self.state_topic = format!("{}{}", prefix, topic);
+        }
+        if let Some(topic) = self
+            .json_attributes_topic
+            .as_deref()
+            .and_then(|topic| topic.strip_prefix(DISCOVERY_PREFIX))
+        {
+            self.json_attributes_topic = Some(format!("{}{}", prefix, topic));
+        }
+        self
+    }
+
+    /// Topic holding the attributes of the sensor, if any.
+    pub fn get_json_attributes_topic(self) -> Option<String> {
+        self.json_attributes_topic
+    }
+
+    /// Sets the time after which Home Assistant marks the sensor without
+    /// update as unavailable, for the sensors that expire.
+    pub fn with_expire_after(mut self, expire_after: Duration) -> Self {
+        if self.expire_after.is_some() {
+            self.expire_after = Some(expire_after.as_secs());
+        }
+        self
+    }
+
+    /// Attaches the sensor to the device of its host.
+    pub fn with_device(mut self, device: HomeAssistantDevice) -> Self {
+        self.device = Some(device);
+        self
+    }
+
     pub fn get_config_topic(self) -> String {
-        self.state_topic.replace("/state", "/config")
+        format!(
+            "{}/{}/{}/config",
+            self.discovery_prefix,
+            self.component.as_str(),
+            self.unique_id
+        )
     }
 
     pub fn get_state_topic(self) -> String {
@@ -25,13 +185,26 @@ impl HomeAssistantDiscoveryConfig {
     pub fn get_name(self) -> String {
         self.name.clone()
     }
+
+    /// Converts a byte count into the advertised unit of measurement,
+    /// rounded to two decimals.
+    pub fn scale_bytes(&self, bytes: u64) -> f64 {
+        let divisor = match self.unit_of_measurement.as_deref().unwrap_or_default() {
+            "kB" => 1e3,
+            "MB" => 1e6,
+            "GB" => 1e9,
+            "TB" => 1e12,
+            _ => 1.0,
+        };
+        (bytes as f64 / divisor * 100.0).round() / 100.0
+    }
 }
 
 fn get_discovery_config_used(
     host: &String,
     category: &Category,
-    _total_bytes: u64, // total_bytes is not used for now, but passed for future use
-) -> HomeAssistantDiscoveryConfig {
+    _total_bytes: u64, // the total is published as a JSON attribute of the state
+) -> Result<HomeAssistantDiscoveryConfig, MetricError> {
     let (name, sensor_name, icon, unit_of_measurement) = match category {
         Category::Disk => (
             format!("{}-{}", host, "disk_used").to_string(),
@@ -51,33 +224,98 @@ fn get_discovery_config_used(
             "mdi:swap-horizontal".to_string(),
             "MB".to_string(),
         ),
-        Category::Cpu => {
-            unreachable!("get_discovery_config_used should not be called for CPU")
-        }
+        Category::Filesystem(mount) => (
+            format!("{}-disk_{}_used", host, readable(mount)),
+            format!("disk_{}_used", mount_slug(mount)),
+            "mdi:harddisk".to_string(),
+            "GB".to_string(),
+        ),
+        // The CPU and the categories without a total have no used value
+        _ => return Err(MetricError::Unsupported(category.clone())),
     };
     let unique_id = format!("{}{}", host, sensor_name).to_lowercase();
-    let state_topic = format!("homeassistant/sensor/{}/state", &unique_id);
+    let state_topic = format!("{}/sensor/{}/state", DISCOVERY_PREFIX, &unique_id);
     let value_template = "{{ value_json.value }}".to_string();
     let state_class = "measurement".to_string();
-    HomeAssistantDiscoveryConfig {
+    let json_attributes_topic = Some(state_topic.clone());
+    let json_attributes_template = Some("{{ {'total': value_json.total} | tojson }}".to_string());
+    Ok(HomeAssistantDiscoveryConfig {
+        component: Component::Sensor,
+        discovery_prefix: DISCOVERY_PREFIX.to_string(),
         name,
         unique_id,
         state_topic,
-        unit_of_measurement,
+        unit_of_measurement: Some(unit_of_measurement),
         value_template,
-        state_class,
+        state_class: Some(state_class),
         icon,
-        expire_after: 300,
-    }
+        expire_after: Some(EXPIRE_AFTER),
+        device_class: None,
+        payload_on: None,
+        payload_off: None,
+        availability_topic: availability_topic(host),
+        payload_available: PAYLOAD_ONLINE.to_string(),
+        payload_not_available: PAYLOAD_OFFLINE.to_string(),
+        json_attributes_topic,
+        json_attributes_template,
+        device: None,
+    })
 }
 
-impl From<&Metric> for HomeAssistantDiscoveryConfig {
-    fn from(metric: &Metric) -> Self {
+/// The discovery config of a metric, failing for a percent, used or window
+/// metric of a category that has no usage sensor.
+impl TryFrom<&Metric> for HomeAssistantDiscoveryConfig {
+    type Error = MetricError;
+
+    fn try_from(metric: &Metric) -> Result<Self, Self::Error> {
         match metric {
             Metric::Percent(host, category, _) => get_discovery_config_percent(host, category),
             Metric::Used(host, category, _used, total) => {
                 get_discovery_config_used(host, category, *total)
             }
+            Metric::Window(host, category, _) => get_discovery_config_window(host, category),
+            Metric::Value(host, category, quantity, _) => {
+                Ok(get_discovery_config_value(host, category, *quantity))
+            }
+            Metric::Top(host, category, _) => get_discovery_config_percent(host, category),
+        }
+    }
+}
+
+/// A binary sensor per alert rule and category, on while the rule is at WARN
+/// or CRIT. Its state is the retained alert topic, with the level and usage
+/// as attributes.
+impl From<&AlertTransition> for HomeAssistantDiscoveryConfig {
+    fn from(transition: &AlertTransition) -> Self {
+        let host = &transition.host;
+        let entity = format!("{}_{}", transition.rule, transition.category.slug());
+        let label = format!("{}_{}", transition.rule, transition.category.label());
+        let state_topic = alert_topic(host, &transition.rule, &transition.category);
+        HomeAssistantDiscoveryConfig {
+            component: Component::BinarySensor,
+            discovery_prefix: DISCOVERY_PREFIX.to_string(),
+            name: format!("{}-{}", host, label),
+            unique_id: format!("{}{}_problem", host, entity).to_lowercase(),
+            state_topic: state_topic.clone(),
+            unit_of_measurement: None,
+            value_template: format!(
+                "{{{{ '{}' if value_json.level != 'OK' else '{}' }}}}",
+                PAYLOAD_PROBLEM, PAYLOAD_NO_PROBLEM
+            ),
+            state_class: None,
+            icon: "mdi:alert".to_string(),
+            expire_after: None,
+            device_class: Some("problem".to_string()),
+            payload_on: Some(PAYLOAD_PROBLEM.to_string()),
+            payload_off: Some(PAYLOAD_NO_PROBLEM.to_string()),
+            availability_topic: availability_topic(host),
+            payload_available: PAYLOAD_ONLINE.to_string(),
+            payload_not_available: PAYLOAD_OFFLINE.to_string(),
+            json_attributes_topic: Some(state_topic),
+            json_attributes_template: Some(
+                "{{ {'level': value_json.level, 'value': value_json.value} | tojson }}".to_string(),
+            ),
+            device: None,
         }
     }
 }
@@ -85,7 +323,7 @@ impl From<&Metric> for HomeAssistantDiscoveryConfig {
 fn get_discovery_config_percent(
     host: &String,
     category: &Category,
-) -> HomeAssistantDiscoveryConfig {
+) -> Result<HomeAssistantDiscoveryConfig, MetricError> {
     let (name, sensor_name, icon) = match category {
         Category::Disk => (
             format!("{}-{}", host, "disk").to_string(),
@@ -107,40 +345,274 @@ fn get_discovery_config_percent(
             "swapUsePercent".to_string(),
             "mdi:swap-horizontal".to_string(), // Standard MDI icon for swap
         ),
+        Category::Filesystem(mount) => (
+            format!("{}-disk_{}", host, readable(mount)),
+            format!("disk_{}_usePercent", mount_slug(mount)),
+            "mdi:harddisk".to_string(),
+        ),
+        Category::Core(index) => (
+            format!("{}-cpu_core{}", host, index),
+            format!("cpu_core{}_usePercent", index),
+            "mdi:cpu-64-bit".to_string(),
+        ),
+        _ => return Err(MetricError::Unsupported(category.clone())),
     };
     let unique_id = format!("{}{}", host, sensor_name).to_lowercase();
-    let state_topic = format!("homeassistant/sensor/{}/state", &unique_id);
+    let state_topic = format!("{}/sensor/{}/state", DISCOVERY_PREFIX, &unique_id);
     let unit_of_measurement = "%".to_string();
     let value_template = "{{ value_json.value }}".to_string();
     let state_class = "measurement".to_string();
-    HomeAssistantDiscoveryConfig {
+    // The top processes, when collected, are attributes of the CPU and
+    // memory usage
+    let json_attributes_topic = match category {
+        Category::Cpu | Category::Memory => Some(processes_topic(host, category)),
+        _ => None,
+    };
+    Ok(HomeAssistantDiscoveryConfig {
+        component: Component::Sensor,
+        discovery_prefix: DISCOVERY_PREFIX.to_string(),
         name,
         unique_id,
         state_topic,
-        unit_of_measurement,
+        unit_of_measurement: Some(unit_of_measurement),
         value_template,
-        state_class,
+        state_class: Some(state_class),
         icon,
-        expire_after: 300,
+        expire_after: Some(EXPIRE_AFTER),
+        device_class: None,
+        payload_on: None,
+        payload_off: None,
+        availability_topic: availability_topic(host),
+        payload_available: PAYLOAD_ONLINE.to_string(),
+        payload_not_available: PAYLOAD_OFFLINE.to_string(),
+        json_attributes_topic,
+        json_attributes_template: None,
+        device: None,
+    })
+}
+
+/// The usage of a category over the publish window: its mean as state, with
+/// the minimum, maximum and 95th percentile as attributes.
+fn get_discovery_config_window(
+    host: &String,
+    category: &Category,
+) -> Result<HomeAssistantDiscoveryConfig, MetricError> {
+    let percent = get_discovery_config_percent(host, category)?;
+    let unique_id = format!("{}_window", percent.unique_id);
+    let state_topic = format!("{}/sensor/{}/state", DISCOVERY_PREFIX, &unique_id);
+    Ok(HomeAssistantDiscoveryConfig {
+        name: format!("{}_window", percent.name),
+        unique_id,
+        json_attributes_topic: Some(state_topic.clone()),
+        json_attributes_template: Some(
+            "{{ {'min': value_json.min, 'max': value_json.max, 'p95': value_json.p95, \
+             'samples': value_json.samples} | tojson }}"
+                .to_string(),
+        ),
+        state_topic,
+        icon: "mdi:chart-bell-curve".to_string(),
+        ..percent
+    })
+}
+
+/// A sensor per quantity. Host-wide categories are named after the quantity
+/// alone (e.g. `load1`), the others after their label and the quantity, and
+/// identified by their slug instead. Whether a watched process runs is a
+/// `running` binary sensor.
+fn get_discovery_config_value(
+    host: &String,
+    category: &Category,
+    quantity: Quantity,
+) -> HomeAssistantDiscoveryConfig {
+    let (sensor_name, label) = match category {
+        Category::Load | Category::Uptime => {
+            (quantity.name().to_string(), quantity.name().to_string())
+        }
+        _ => (
+            format!("{}_{}", category.slug(), quantity.name()),
+            format!("{}_{}", category.label(), quantity.name()),
+        ),
+    };
+    let (icon, device_class, unit_of_measurement, state_class, value_template) = match quantity {
+        Quantity::Load1 | Quantity::Load5 | Quantity::Load15 => (
+            "mdi:gauge",
+            None,
+            None,
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::Uptime => (
+            "mdi:timer-outline",
+            Some("duration"),
+            Some("s"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        // Home Assistant expects timestamps as ISO 8601 strings
+        Quantity::BootTime => (
+            "mdi:restart",
+            Some("timestamp"),
+            None,
+            None,
+            "{{ value_json.value | int | timestamp_custom('%Y-%m-%dT%H:%M:%S+00:00', false) }}",
+        ),
+        Quantity::RxBytes | Quantity::TxBytes => (
+            "mdi:network",
+            Some("data_rate"),
+            Some("B/s"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::RxPackets | Quantity::TxPackets => (
+            "mdi:network",
+            None,
+            Some("packets/s"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::RxErrors | Quantity::TxErrors | Quantity::RxDropped | Quantity::TxDropped => (
+            "mdi:network-off",
+            None,
+            None,
+            Some("total_increasing"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::Temperature | Quantity::TemperatureMax | Quantity::TemperatureCritical => (
+            "mdi:thermometer",
+            Some("temperature"),
+            Some("°C"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::Running => (
+            "mdi:application-cog",
+            Some("running"),
+            None,
+            None,
+            "{{ 'ON' if value_json.value > 0 else 'OFF' }}",
+        ),
+        Quantity::Instances => (
+            "mdi:counter",
+            None,
+            None,
+            Some("measurement"),
+            "{{ value_json.value | int }}",
+        ),
+        Quantity::ProcessCpu | Quantity::CpuUsage => (
+            "mdi:cpu-64-bit",
+            None,
+            Some("%"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::ProcessMemory | Quantity::MemoryUsed | Quantity::MemoryMax => (
+            "mdi:memory",
+            Some("data_size"),
+            Some("B"),
+            Some("measurement"),
+            "{{ value_json.value | int }}",
+        ),
+        Quantity::MemoryUsage => (
+            "mdi:memory",
+            None,
+            Some("%"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::Throttled => (
+            "mdi:speedometer-slow",
+            None,
+            None,
+            Some("total_increasing"),
+            "{{ value_json.value | int }}",
+        ),
+        Quantity::SomeAvg10
+        | Quantity::SomeAvg60
+        | Quantity::SomeAvg300
+        | Quantity::FullAvg10
+        | Quantity::FullAvg60
+        | Quantity::FullAvg300 => (
+            "mdi:timer-sand",
+            None,
+            Some("%"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+        Quantity::IoRead | Quantity::IoWrite => (
+            "mdi:harddisk",
+            Some("data_rate"),
+            Some("B/s"),
+            Some("measurement"),
+            "{{ value_json.value }}",
+        ),
+    };
+    let (component, payload_on, payload_off) = match quantity {
+        Quantity::Running => (
+            Component::BinarySensor,
+            Some(PAYLOAD_RUNNING.to_string()),
+            Some(PAYLOAD_NOT_RUNNING.to_string()),
+        ),
+        _ => (Component::Sensor, None, None),
+    };
+    let unique_id = format!("{}{}", host, sensor_name).to_lowercase();
+    let state_topic = format!(
+        "{}/{}/{}/state",
+        DISCOVERY_PREFIX,
+        component.as_str(),
+        &unique_id
+    );
+    HomeAssistantDiscoveryConfig {
+        component,
+        discovery_prefix: DISCOVERY_PREFIX.to_string(),
+        name: format!("{}-{}", host, label),
+        unique_id,
+        state_topic,
+        unit_of_measurement: unit_of_measurement.map(str::to_string),
+        value_template: value_template.to_string(),
+        state_class: state_class.map(str::to_string),
+        icon: icon.to_string(),
+        expire_after: Some(EXPIRE_AFTER),
+        device_class: device_class.map(str::to_string),
+        payload_on,
+        payload_off,
+        availability_topic: availability_topic(host),
+        payload_available: PAYLOAD_ONLINE.to_string(),
+        payload_not_available: PAYLOAD_OFFLINE.to_string(),
+        json_attributes_topic: None,
+        json_attributes_template: None,
+        device: None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::metrics::models::{Category, Metric, Percentage};
+    use crate::domain::alerts::models::AlertLevel;
+    use crate::domain::metrics::models::{Category, Metric, Percentage, Window};
+    use std::time::SystemTime;
 
     #[test]
     fn test_get_config_topic() {
         let config = HomeAssistantDiscoveryConfig {
+            component: Component::Sensor,
+            discovery_prefix: DISCOVERY_PREFIX.to_string(),
             name: "test-sensor".to_string(),
             unique_id: "test-id".to_string(),
             state_topic: "homeassistant/sensor/test-id/state".to_string(),
-            unit_of_measurement: "%".to_string(),
+            unit_of_measurement: Some("%".to_string()),
             value_template: "{{ value_json.value }}".to_string(),
-            state_class: "measurement".to_string(),
+            state_class: Some("measurement".to_string()),
             icon: "mdi:cpu-64-bit".to_string(),
-            expire_after: 300,
+            expire_after: Some(EXPIRE_AFTER),
+            device_class: None,
+            payload_on: None,
+            payload_off: None,
+            availability_topic: "srvstat/test-host/availability".to_string(),
+            payload_available: "online".to_string(),
+            payload_not_available: "offline".to_string(),
+            json_attributes_topic: None,
+            json_attributes_template: None,
+            device: None,
         };
         let config_topic = config.clone().get_config_topic();
         assert_eq!(
@@ -152,14 +624,25 @@ mod tests {
     #[test]
     fn test_get_state_topic() {
         let config = HomeAssistantDiscoveryConfig {
+            component: Component::Sensor,
+            discovery_prefix: DISCOVERY_PREFIX.to_string(),
             name: "test-sensor".to_string(),
             unique_id: "test-id".to_string(),
             state_topic: "homeassistant/sensor/test-id/state".to_string(),
-            unit_of_measurement: "%".to_string(),
+            unit_of_measurement: Some("%".to_string()),
             value_template: "{{ value_json.value }}".to_string(),
-            state_class: "measurement".to_string(),
+            state_class: Some("measurement".to_string()),
             icon: "mdi:cpu-64-bit".to_string(),
-            expire_after: 300,
+            expire_after: Some(EXPIRE_AFTER),
+            device_class: None,
+            payload_on: None,
+            payload_off: None,
+            availability_topic: "srvstat/test-host/availability".to_string(),
+            payload_available: "online".to_string(),
+            payload_not_available: "offline".to_string(),
+            json_attributes_topic: None,
+            json_attributes_template: None,
+            device: None,
         };
         let state_topic = config.clone().get_state_topic();
         assert_eq!(
@@ -171,24 +654,423 @@ mod tests {
     #[test]
     fn test_get_name() {
         let config = HomeAssistantDiscoveryConfig {
+            component: Component::Sensor,
+            discovery_prefix: DISCOVERY_PREFIX.to_string(),
             name: "test-sensor".to_string(),
             unique_id: "test-id".to_string(),
             state_topic: "homeassistant/sensor/test-id/state".to_string(),
-            unit_of_measurement: "%".to_string(),
+            unit_of_measurement: Some("%".to_string()),
             value_template: "{{ value_json.value }}".to_string(),
-            state_class: "measurement".to_string(),
+            state_class: Some("measurement".to_string()),
             icon: "mdi:cpu-64-bit".to_string(),
-            expire_after: 300,
+            expire_after: Some(EXPIRE_AFTER),
+            device_class: None,
+            payload_on: None,
+            payload_off: None,
+            availability_topic: "srvstat/test-host/availability".to_string(),
+            payload_available: "online".to_string(),
+            payload_not_available: "offline".to_string(),
+            json_attributes_topic: None,
+            json_attributes_template: None,
+            device: None,
         };
         let name = config.clone().get_name();
         assert_eq!(name, "test-sensor".to_string());
     }
 
+    #[test]
+    fn test_unsupported_metric() {
+        let host = "test-host".to_string();
+        let unsupported = [
+            Metric::Used(host.clone(), Category::Cpu, 1, 2),
+            Metric::Used(host.clone(), Category::Core(0), 1, 2),
+            Metric::Used(host.clone(), Category::Load, 1, 2),
+            Metric::Percent(
+                host.clone(),
+                Category::Interface("eth0".to_string()),
+                Percentage(1),
+            ),
+        ];
+        for metric in &unsupported {
+            assert!(matches!(
+                HomeAssistantDiscoveryConfig::try_from(metric),
+                Err(MetricError::Unsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_availability_topic() {
+        assert_eq!(
+            availability_topic("Test-Host"),
+            "srvstat/test-host/availability"
+        );
+    }
+
+    #[test]
+    fn test_alert_to_binary_sensor_config() {
+        let transition = AlertTransition {
+            rule: "disk_full".to_string(),
+            host: "Tux".to_string(),
+            category: Category::Filesystem("/home".to_string()),
+            previous: None,
+            level: AlertLevel::Warn,
+            value: 85,
+            timestamp: SystemTime::UNIX_EPOCH,
+        };
+        let config = HomeAssistantDiscoveryConfig::from(&transition)
+            .with_discovery_prefix("custom")
+            .with_expire_after(Duration::from_secs(600));
+
+        assert_eq!(
+            config.clone().get_config_topic(),
+            "custom/binary_sensor/tuxdisk_full_disk_home_problem/config"
+        );
+        assert_eq!(
+            config.clone().get_state_topic(),
+            "srvstat/tux/alerts/disk_full/disk_home"
+        );
+        let payload = serde_json::to_value(&config).unwrap();
+        assert_eq!(payload["device_class"], "problem");
+        assert_eq!(payload["payload_on"], "ON");
+        assert_eq!(payload["payload_off"], "OFF");
+        assert_eq!(
+            payload["value_template"],
+            "{{ 'ON' if value_json.level != 'OK' else 'OFF' }}"
+        );
+        assert!(payload.get("unit_of_measurement").is_none());
+        assert!(payload.get("state_class").is_none());
+        assert!(payload.get("expire_after").is_none());
+        assert!(payload.get("component").is_none());
+    }
+
+    #[test]
+    fn test_with_expire_after() {
+        let metric = Metric::Percent("tux".to_string(), Category::Cpu, Percentage(5));
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(serde_json::to_value(&config).unwrap()["expire_after"], 300);
+        let config = config.with_expire_after(Duration::from_secs(780));
+        assert_eq!(serde_json::to_value(&config).unwrap()["expire_after"], 780);
+    }
+
+    #[test]
+    fn test_sensor_payload_has_no_binary_sensor_fields() {
+        let metric = Metric::Percent("tux".to_string(), Category::Cpu, Percentage(5));
+        let payload =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&metric).unwrap()).unwrap();
+        assert_eq!(payload["unit_of_measurement"], "%");
+        assert!(payload.get("payload_on").is_none());
+        assert!(payload.get("device_class").is_none());
+    }
+
+    #[test]
+    fn test_processes_attributes() {
+        let host = "Tux".to_string();
+        let metric = Metric::Top(host.clone(), Category::Memory, vec![]);
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)
+            .unwrap()
+            .with_discovery_prefix("custom");
+        assert_eq!(
+            config.clone().get_state_topic(),
+            "custom/sensor/tuxmemoryusepercent/state"
+        );
+        assert_eq!(
+            config.get_json_attributes_topic().as_deref(),
+            Some("srvstat/tux/processes/memory")
+        );
+
+        let percent = Metric::Percent(host.clone(), Category::Cpu, Percentage(5));
+        let config = HomeAssistantDiscoveryConfig::try_from(&percent).unwrap();
+        assert_eq!(
+            config.get_json_attributes_topic(),
+            Some(processes_topic(&host, &Category::Cpu))
+        );
+        let used = Metric::Used(host, Category::Swap, 1024, 2048);
+        let config = HomeAssistantDiscoveryConfig::try_from(&used)
+            .unwrap()
+            .with_discovery_prefix("custom");
+        assert_eq!(
+            config.get_json_attributes_topic().as_deref(),
+            Some("custom/sensor/tuxswapused/state")
+        );
+    }
+
+    #[test]
+    fn test_alert_topic() {
+        assert_eq!(
+            alert_topic(
+                "Tux",
+                "disk_full",
+                &Category::Filesystem("/home".to_string())
+            ),
+            "srvstat/tux/alerts/disk_full/disk_home"
+        );
+    }
+
+    #[test]
+    fn test_metric_to_config_availability() {
+        let host = "test-host".to_string();
+        let metrics = [
+            Metric::Percent(host.clone(), Category::Disk, Percentage::new(50).unwrap()),
+            Metric::Used(host.clone(), Category::Memory, 4096, 8192),
+        ];
+        for metric in &metrics {
+            let config = HomeAssistantDiscoveryConfig::try_from(metric).unwrap();
+            let json = serde_json::to_value(&config).unwrap();
+            assert_eq!(json["availability_topic"], "srvstat/test-host/availability");
+            assert_eq!(json["payload_available"], "online");
+            assert_eq!(json["payload_not_available"], "offline");
+        }
+    }
+
+    #[test]
+    fn test_scale_bytes() {
+        let host = "test-host".to_string();
+        let disk: HomeAssistantDiscoveryConfig =
+            (&Metric::Used(host.clone(), Category::Disk, 0, 0))
+                .try_into()
+                .unwrap();
+        let memory: HomeAssistantDiscoveryConfig =
+            (&Metric::Used(host.clone(), Category::Memory, 0, 0))
+                .try_into()
+                .unwrap();
+
+        assert_eq!(disk.scale_bytes(500_000_000_000), 500.0);
+        assert_eq!(disk.scale_bytes(1_234_567_890), 1.23);
+        assert_eq!(memory.scale_bytes(4_096_000_000), 4096.0);
+        assert_eq!(memory.scale_bytes(1_555_000), 1.56);
+    }
+
+    #[test]
+    fn test_metric_used_json_attributes() {
+        let host = "test-host".to_string();
+        let metric = Metric::Used(host.clone(), Category::Swap, 1024, 2048);
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)
+            .unwrap()
+            .with_discovery_prefix("ha");
+        let json = serde_json::to_value(&config).unwrap();
+
+        assert_eq!(
+            json["json_attributes_topic"],
+            "ha/sensor/test-hostswapused/state"
+        );
+        assert_eq!(
+            json["json_attributes_template"],
+            "{{ {'total': value_json.total} | tojson }}"
+        );
+
+        let percent = Metric::Percent(host, Category::Swap, Percentage::new(50).unwrap());
+        let json = serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&percent).unwrap())
+            .unwrap();
+        assert!(json.get("json_attributes_topic").is_none());
+    }
+
+    #[test]
+    fn test_metric_window() {
+        let window = Window {
+            min: Percentage(5),
+            max: Percentage(95),
+            mean: Percentage(30),
+            p95: Percentage(90),
+            samples: 12,
+        };
+        let metric = Metric::Window("test-host".to_string(), Category::Core(1), window);
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)
+            .unwrap()
+            .with_discovery_prefix("ha");
+        assert_eq!(config.name, "test-host-cpu_core1_window");
+        assert_eq!(
+            config.clone().get_config_topic(),
+            "ha/sensor/test-hostcpu_core1_usepercent_window/config"
+        );
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["unit_of_measurement"], "%");
+        assert_eq!(
+            json["json_attributes_topic"],
+            "ha/sensor/test-hostcpu_core1_usepercent_window/state"
+        );
+        assert_eq!(json["state_topic"], json["json_attributes_topic"]);
+    }
+
+    #[test]
+    fn test_metric_value() {
+        let host = "test-host".to_string();
+        let load = Metric::Value(host.clone(), Category::Load, Quantity::Load15, 0.5);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&load).unwrap()).unwrap();
+        assert_eq!(json["name"], "test-host-load15");
+        assert_eq!(json["unique_id"], "test-hostload15");
+        assert_eq!(json["state_class"], "measurement");
+        assert!(json.get("unit_of_measurement").is_none());
+
+        let uptime = Metric::Value(host.clone(), Category::Uptime, Quantity::Uptime, 60.0);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&uptime).unwrap()).unwrap();
+        assert_eq!(json["device_class"], "duration");
+        assert_eq!(json["unit_of_measurement"], "s");
+
+        let boot = Metric::Value(host, Category::Uptime, Quantity::BootTime, 1.7e9);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&boot).unwrap()).unwrap();
+        assert_eq!(json["device_class"], "timestamp");
+        assert_eq!(
+            json["state_topic"],
+            "homeassistant/sensor/test-hostboot_time/state"
+        );
+        assert!(json.get("state_class").is_none());
+        assert!(json.get("unit_of_measurement").is_none());
+    }
+
+    #[test]
+    fn test_metric_network_value() {
+        let interface = Category::Interface("eth0".to_string());
+        let rate = Metric::Value("nas".to_string(), interface.clone(), Quantity::RxBytes, 1e3);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&rate).unwrap()).unwrap();
+        assert_eq!(json["name"], "nas-net_eth0_rx_bytes");
+        assert_eq!(json["device_class"], "data_rate");
+        assert_eq!(json["unit_of_measurement"], "B/s");
+
+        let errors = Metric::Value("nas".to_string(), interface, Quantity::TxErrors, 2.0);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&errors).unwrap()).unwrap();
+        assert_eq!(json["unique_id"], "nasnet_eth0_tx_errors");
+        assert_eq!(json["state_class"], "total_increasing");
+    }
+
+    #[test]
+    fn test_metric_temperature() {
+        let sensor = Category::Sensor("coretemp Core 0".to_string());
+        let metric = Metric::Value(
+            "nas".to_string(),
+            sensor,
+            Quantity::TemperatureCritical,
+            100.0,
+        );
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&metric).unwrap()).unwrap();
+        assert_eq!(
+            json["name"],
+            "nas-temp_coretemp_core_0_temperature_critical"
+        );
+        assert_eq!(json["device_class"], "temperature");
+        assert_eq!(json["unit_of_measurement"], "°C");
+    }
+
+    #[test]
+    fn test_metric_watched() {
+        let watched = Category::Watched("nginx".to_string());
+        let running = Metric::Value("nas".to_string(), watched.clone(), Quantity::Running, 1.0);
+        let config = HomeAssistantDiscoveryConfig::try_from(&running)
+            .unwrap()
+            .with_discovery_prefix("custom");
+        assert_eq!(
+            config.clone().get_config_topic(),
+            "custom/binary_sensor/naswatch_nginx_running/config"
+        );
+        assert_eq!(
+            config.clone().get_state_topic(),
+            "custom/binary_sensor/naswatch_nginx_running/state"
+        );
+        let json = serde_json::to_value(config).unwrap();
+        assert_eq!(json["device_class"], "running");
+        assert_eq!(json["payload_on"], PAYLOAD_RUNNING);
+        assert_eq!(
+            json["value_template"],
+            "{{ 'ON' if value_json.value > 0 else 'OFF' }}"
+        );
+        assert!(json.get("state_class").is_none());
+
+        let memory = Metric::Value("nas".to_string(), watched, Quantity::ProcessMemory, 4096.0);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&memory).unwrap()).unwrap();
+        assert_eq!(json["name"], "nas-watch_nginx_process_memory");
+        assert_eq!(json["device_class"], "data_size");
+        assert!(json.get("payload_on").is_none());
+    }
+
+    #[test]
+    fn test_metric_pressure() {
+        let psi = Category::Psi("memory".to_string());
+        let metric = Metric::Value("nas".to_string(), psi, Quantity::FullAvg60, 1.5);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&metric).unwrap()).unwrap();
+        assert_eq!(json["name"], "nas-psi_memory_full_avg60");
+        assert_eq!(json["unit_of_measurement"], "%");
+        assert_eq!(json["state_class"], "measurement");
+    }
+
+    #[test]
+    fn test_metric_cgroup() {
+        let cgroup = Category::Cgroup("system.slice/nginx.service".to_string());
+        let throttled = Metric::Value("nas".to_string(), cgroup.clone(), Quantity::Throttled, 3.0);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&throttled).unwrap())
+                .unwrap();
+        assert_eq!(
+            json["name"],
+            "nas-cgroup_system_slice_nginx_service_throttled"
+        );
+        assert_eq!(json["state_class"], "total_increasing");
+
+        let read = Metric::Value("nas".to_string(), cgroup, Quantity::IoRead, 512.0);
+        let json =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&read).unwrap()).unwrap();
+        assert_eq!(json["device_class"], "data_rate");
+        assert_eq!(json["unit_of_measurement"], "B/s");
+    }
+
+    #[test]
+    fn test_with_device() {
+        let host = "test-host".to_string();
+        let device =
+            HomeAssistantDevice::new("abc123", &host, "Debian 12 (kernel 6.1.0)".to_string());
+        let metric = Metric::Percent(host.clone(), Category::Cpu, Percentage::new(50).unwrap());
+
+        let without_device =
+            serde_json::to_value(HomeAssistantDiscoveryConfig::try_from(&metric).unwrap()).unwrap();
+        assert!(without_device.get("device").is_none());
+
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)
+            .unwrap()
+            .with_device(device);
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(
+            json["device"]["identifiers"],
+            serde_json::json!(["srvstat_abc123"])
+        );
+        assert_eq!(json["device"]["name"], "test-host");
+        assert_eq!(json["device"]["model"], "Debian 12 (kernel 6.1.0)");
+        assert_eq!(json["device"]["manufacturer"], "srvstat");
+        assert_eq!(json["device"]["sw_version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_with_discovery_prefix() {
+        let metric = Metric::Percent(
+            "test-host".to_string(),
+            Category::Cpu,
+            Percentage::new(50).unwrap(),
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)
+            .unwrap()
+            .with_discovery_prefix("ha");
+
+        assert_eq!(
+            config.clone().get_state_topic(),
+            "ha/sensor/test-hostcpuusepercent/state"
+        );
+        assert_eq!(
+            config.get_config_topic(),
+            "ha/sensor/test-hostcpuusepercent/config"
+        );
+    }
+
     #[test]
     fn test_metric_to_config_conversion_cpu() {
         let host = "test-host".to_string();
         let metric = Metric::Percent(host.clone(), Category::Cpu, Percentage::new(50).unwrap());
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-cpu");
         assert_eq!(config.unique_id, "test-hostcpuusepercent");
@@ -196,7 +1078,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostcpuusepercent/state"
         );
-        assert_eq!(config.unit_of_measurement, "%");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
         assert_eq!(config.icon, "mdi:cpu-64-bit");
     }
 
@@ -204,7 +1086,7 @@ mod tests {
     fn test_metric_to_config_conversion_memory() {
         let host = "test-host".to_string();
         let metric = Metric::Percent(host.clone(), Category::Memory, Percentage::new(50).unwrap());
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-memory");
         assert_eq!(config.unique_id, "test-hostmemoryusepercent");
@@ -212,7 +1094,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostmemoryusepercent/state"
         );
-        assert_eq!(config.unit_of_measurement, "%");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
         assert_eq!(config.icon, "mdi:memory");
     }
 
@@ -220,7 +1102,7 @@ mod tests {
     fn test_metric_to_config_conversion_disk() {
         let host = "test-host".to_string();
         let metric = Metric::Percent(host.clone(), Category::Disk, Percentage::new(50).unwrap());
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-disk");
         assert_eq!(config.unique_id, "test-hostdiskusepercent");
@@ -228,7 +1110,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostdiskusepercent/state"
         );
-        assert_eq!(config.unit_of_measurement, "%");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
         assert_eq!(config.icon, "mdi:harddisk");
     }
 
@@ -236,7 +1118,7 @@ mod tests {
     fn test_metric_to_config_conversion_swap() {
         let host = "test-host".to_string();
         let metric = Metric::Percent(host.clone(), Category::Swap, Percentage::new(75).unwrap());
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-swap");
         assert_eq!(config.unique_id, "test-hostswapusepercent");
@@ -244,15 +1126,79 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostswapusepercent/state"
         );
-        assert_eq!(config.unit_of_measurement, "%");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
         assert_eq!(config.icon, "mdi:swap-horizontal");
     }
 
+    #[test]
+    fn test_metric_to_config_conversion_filesystem() {
+        let host = "test-host".to_string();
+        let category = Category::Filesystem("/mnt/data".to_string());
+        let metric = Metric::Percent(host.clone(), category, Percentage::new(50).unwrap());
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+
+        assert_eq!(config.name, "test-host-disk_mnt_data");
+        assert_eq!(config.unique_id, "test-hostdisk_mnt_data_usepercent");
+        assert_eq!(
+            config.state_topic,
+            "homeassistant/sensor/test-hostdisk_mnt_data_usepercent/state"
+        );
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
+        assert_eq!(config.icon, "mdi:harddisk");
+    }
+
+    #[test]
+    fn test_metric_used_to_config_conversion_filesystem() {
+        let host = "test-host".to_string();
+        let category = Category::Filesystem("/".to_string());
+        let metric = Metric::Used(host.clone(), category, 1, 2);
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+
+        assert_eq!(config.name, "test-host-disk_root_used");
+        assert_eq!(config.unique_id, "test-hostdisk_root_used");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("GB"));
+    }
+
+    #[test]
+    fn test_filesystems_have_distinct_unique_ids() {
+        let unique_id = |mount: &str| {
+            let category = Category::Filesystem(mount.to_string());
+            let metric = Metric::Percent("tux".to_string(), category, Percentage(1));
+            HomeAssistantDiscoveryConfig::try_from(&metric)
+                .unwrap()
+                .unique_id
+        };
+        assert_ne!(unique_id("/"), unique_id("/root"));
+        assert_ne!(unique_id("/mnt/a-b"), unique_id("/mnt/a_b"));
+        assert_eq!(unique_id("/mnt/a-b"), "tuxdisk_mnt_a-2db_usepercent");
+    }
+
+    #[test]
+    fn test_metric_to_config_conversion_core() {
+        let host = "test-host".to_string();
+        let metric = Metric::Percent(
+            host.clone(),
+            Category::Core(2),
+            Percentage::new(50).unwrap(),
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+
+        assert_eq!(config.name, "test-host-cpu_core2");
+        assert_eq!(config.unique_id, "test-hostcpu_core2_usepercent");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("%"));
+        assert_eq!(config.icon, "mdi:cpu-64-bit");
+    }
+
     #[test]
     fn test_metric_used_to_config_conversion_disk() {
         let host = "test-host".to_string();
-        let metric = Metric::Used(host.clone(), Category::Disk, 500_000_000_000, 1_000_000_000_000);
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let metric = Metric::Used(
+            host.clone(),
+            Category::Disk,
+            500_000_000_000,
+            1_000_000_000_000,
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-disk_used");
         assert_eq!(config.unique_id, "test-hostdiskused");
@@ -260,7 +1206,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostdiskused/state"
         );
-        assert_eq!(config.unit_of_measurement, "GB");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("GB"));
         assert_eq!(config.icon, "mdi:harddisk");
     }
 
@@ -268,7 +1214,7 @@ mod tests {
     fn test_metric_used_to_config_conversion_memory() {
         let host = "test-host".to_string();
         let metric = Metric::Used(host.clone(), Category::Memory, 4096, 8192);
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-memory_used");
         assert_eq!(config.unique_id, "test-hostmemoryused");
@@ -276,7 +1222,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostmemoryused/state"
         );
-        assert_eq!(config.unit_of_measurement, "MB");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("MB"));
         assert_eq!(config.icon, "mdi:memory");
     }
 
@@ -284,7 +1230,7 @@ mod tests {
     fn test_metric_used_to_config_conversion_swap() {
         let host = "test-host".to_string();
         let metric = Metric::Used(host.clone(), Category::Swap, 1024, 2048);
-        let config: HomeAssistantDiscoveryConfig = (&metric).into();
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
 
         assert_eq!(config.name, "test-host-swap_used");
         assert_eq!(config.unique_id, "test-hostswapused");
@@ -292,7 +1238,7 @@ mod tests {
             config.state_topic,
             "homeassistant/sensor/test-hostswapused/state"
         );
-        assert_eq!(config.unit_of_measurement, "MB");
+        assert_eq!(config.unit_of_measurement.as_deref(), Some("MB"));
         assert_eq!(config.icon, "mdi:swap-horizontal");
     }
 }
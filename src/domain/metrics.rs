@@ -1,2 +1,4 @@
+pub mod change_filter;
 pub mod metric_service;
 pub mod models;
+pub mod window_aggregator;
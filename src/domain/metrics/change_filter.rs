@@ -0,0 +1,234 @@
+use crate::domain::metrics::models::{Category, Metric, Percentage};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Last published reading of a sensor.
+#[derive(Debug, Clone)]
+struct Published {
+    // Percentages subject to the deadband
+    percents: Vec<f64>,
+    total: Option<u64>,
+    at: Instant,
+}
+
+/// Decides which readings are worth publishing.
+///
+/// A reading is published when its usage moved by at least the deadband of
+/// its category (in percentage points, or in the unit of other quantities; any
+/// change by default), when the total changed, or when the sensor was last
+/// published `heartbeat` ago. Used readings are compared by their usage
+/// percentage, and windows by each of their statistics.
+#[derive(Debug)]
+pub struct ChangeFilter {
+    deadbands: BTreeMap<Category, f64>,
+    heartbeat: Duration,
+    // Keyed by category and kind of reading
+    published: HashMap<(Category, &'static str), Published>,
+}
+
+impl ChangeFilter {
+    /// `deadbands` are given per configured category, e.g. `Disk` also
+    /// applying to every filesystem.
+    pub fn new(deadbands: BTreeMap<Category, f64>, heartbeat: Duration) -> Self {
+        ChangeFilter {
+            deadbands,
+            heartbeat,
+            published: HashMap::new(),
+        }
+    }
+
+    /// Whether `metric` should be published at `now`, recording it if so.
+    pub fn should_publish(&mut self, metric: &Metric, now: Instant) -> bool {
+        let (category, kind, percents, total) = match metric {
+            Metric::Percent(_, category, Percentage(value)) => {
+                (category, "percent", vec![f64::from(*value)], None)
+            }
+            Metric::Used(_, category, used, total) => {
+                (category, "used", vec![percent(*used, *total)], Some(*total))
+            }
+            Metric::Window(_, category, window) => (
+                category,
+                "window",
+                [&window.min, &window.max, &window.mean, &window.p95]
+                    .iter()
+                    .map(|Percentage(value)| f64::from(*value))
+                    .collect(),
+                None,
+            ),
+            Metric::Value(_, category, quantity, value) => {
+                (category, quantity.name(), vec![*value], None)
+            }
+            // The ranking changes at nearly every collection
+            Metric::Top(..) => return true,
+        };
+        let reading = Published {
+            percents,
+            total,
+            at: now,
+        };
+        let key = (category.clone(), kind);
+        let deadband = self
+            .deadbands
+            .get(&category.selector())
+            .copied()
+            .unwrap_or(0.0);
+        let publish = match self.published.get(&key) {
+            None => true,
+            Some(last) => {
+                now.duration_since(last.at) >= self.heartbeat
+                    || reading.total != last.total
+                    || reading
+                        .percents
+                        .iter()
+                        .zip(&last.percents)
+                        .any(|(new, old)| {
+                            let delta = (new - old).abs();
+                            delta > 0.0 && delta >= deadband
+                        })
+            }
+        };
+        if publish {
+            self.published.insert(key, reading);
+        }
+        publish
+    }
+
+    /// Forgets what was published, so that every sensor is sent again (e.g.
+    /// after the broker lost its state).
+    pub fn reset(&mut self) {
+        self.published.clear();
+    }
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::models::{Quantity, Window};
+
+    fn disk(value: u8) -> Metric {
+        Metric::Percent("tux".to_string(), Category::Disk, Percentage(value))
+    }
+
+    fn filter() -> ChangeFilter {
+        ChangeFilter::new(
+            BTreeMap::from([(Category::Disk, 2.0), (Category::Temperature, 0.5)]),
+            Duration::from_secs(240),
+        )
+    }
+
+    #[test]
+    fn test_deadband() {
+        let mut filter = filter();
+        let now = Instant::now();
+        assert!(filter.should_publish(&disk(50), now));
+        assert!(!filter.should_publish(&disk(50), now));
+        assert!(!filter.should_publish(&disk(51), now));
+        assert!(filter.should_publish(&disk(52), now));
+        assert!(!filter.should_publish(&disk(51), now));
+    }
+
+    #[test]
+    fn test_deadband_applies_to_filesystems() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let home = |value| {
+            Metric::Percent(
+                "tux".to_string(),
+                Category::Filesystem("/home".to_string()),
+                Percentage(value),
+            )
+        };
+        assert!(filter.should_publish(&home(10), now));
+        assert!(!filter.should_publish(&home(11), now));
+    }
+
+    #[test]
+    fn test_any_change_without_deadband() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let cpu = |value| Metric::Percent("tux".to_string(), Category::Cpu, Percentage(value));
+        assert!(filter.should_publish(&cpu(10), now));
+        assert!(!filter.should_publish(&cpu(10), now));
+        assert!(filter.should_publish(&cpu(11), now));
+    }
+
+    #[test]
+    fn test_used_readings() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let used = |used, total| Metric::Used("tux".to_string(), Category::Disk, used, total);
+        assert!(filter.should_publish(&used(500, 1000), now));
+        // The percent sensor is tracked on its own
+        assert!(filter.should_publish(&disk(50), now));
+        assert!(!filter.should_publish(&used(510, 1000), now));
+        assert!(filter.should_publish(&used(520, 1000), now));
+        assert!(filter.should_publish(&used(520, 2000), now));
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let window = |max| {
+            Metric::Window(
+                "tux".to_string(),
+                Category::Disk,
+                Window {
+                    min: Percentage(10),
+                    max: Percentage(max),
+                    mean: Percentage(20),
+                    p95: Percentage(30),
+                    samples: 12,
+                },
+            )
+        };
+        assert!(filter.should_publish(&window(40), now));
+        assert!(filter.should_publish(&disk(20), now));
+        assert!(!filter.should_publish(&window(41), now));
+        assert!(filter.should_publish(&window(42), now));
+    }
+
+    #[test]
+    fn test_fractional_deadband() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let sensor = Category::Sensor("nvme Composite".to_string());
+        let temperature = |value| {
+            Metric::Value(
+                "tux".to_string(),
+                sensor.clone(),
+                Quantity::Temperature,
+                value,
+            )
+        };
+        assert!(filter.should_publish(&temperature(40.0), now));
+        assert!(!filter.should_publish(&temperature(40.3), now));
+        assert!(filter.should_publish(&temperature(40.5), now));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut filter = filter();
+        let now = Instant::now();
+        assert!(filter.should_publish(&disk(50), now));
+        assert!(!filter.should_publish(&disk(50), now + Duration::from_secs(239)));
+        assert!(filter.should_publish(&disk(50), now + Duration::from_secs(240)));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut filter = filter();
+        let now = Instant::now();
+        assert!(filter.should_publish(&disk(50), now));
+        filter.reset();
+        assert!(filter.should_publish(&disk(50), now));
+    }
+}
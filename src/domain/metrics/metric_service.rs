@@ -1,8 +1,12 @@
+use crate::domain::alerts::alert_engine::AlertEngine;
 use crate::domain::metrics::models::Category;
-use crate::domain::ports::{MetricProcessor, MetricReader, MetricWriter};
+use crate::domain::metrics::window_aggregator::WindowAggregator;
+use crate::domain::ports::{AlertNotifier, MetricProcessor, MetricReader, MetricWriter, Snapshot};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 // Generic service for reading and writing metrics
-#[derive(Debug, Clone)]
 pub struct MetricService<R, W>
 where
     R: MetricReader,
@@ -10,6 +14,14 @@ where
 {
     reader: R,
     writer: W,
+    alerting: Option<Alerting>,
+    sample_interval: Option<Duration>,
+}
+
+// Alert rules fed with every collection, and where their transitions go
+struct Alerting {
+    engine: Mutex<AlertEngine>,
+    notifiers: Vec<Box<dyn AlertNotifier>>,
 }
 
 impl<R, W> MetricService<R, W>
@@ -19,7 +31,123 @@ where
 {
     // Constructors
     pub fn new(reader: R, writer: W) -> Self {
-        Self { reader, writer }
+        Self {
+            reader,
+            writer,
+            alerting: None,
+            sample_interval: None,
+        }
+    }
+
+    /// Evaluates the rules of `engine` on every collection, reporting the
+    /// level changes to each notifier.
+    pub fn with_alerts(
+        mut self,
+        engine: AlertEngine,
+        notifiers: Vec<Box<dyn AlertNotifier>>,
+    ) -> Self {
+        self.alerting = Some(Alerting {
+            engine: Mutex::new(engine),
+            notifiers,
+        });
+        self
+    }
+
+    /// Samples every `sample_interval` when [run](Self::run), each interval
+    /// then reporting its last sample and the statistics of all of them. A
+    /// zero `sample_interval` samples once per interval.
+    pub fn with_sampling(mut self, sample_interval: Duration) -> Self {
+        self.sample_interval = Some(sample_interval).filter(|sample| !sample.is_zero());
+        self
+    }
+
+    // read and write metrics for every given category, returning the number
+    // of failures
+    pub fn process_all(&self, categories: &[Category]) -> usize {
+        self.report(self.reader.collect(categories))
+    }
+
+    // publish a collection, logging the number of failures
+    fn report(&self, snapshot: Snapshot) -> usize {
+        let failures = self.publish(snapshot);
+        if failures > 0 {
+            eprintln!("{} metric(s) failed during this collection", failures);
+        }
+        failures
+    }
+
+    // report the alert level changes of a collection; a failed notification
+    // is only logged, the level being notified again on its next change
+    fn alert(&self, snapshot: &Snapshot) {
+        let Some(alerting) = &self.alerting else {
+            return;
+        };
+        let transitions = alerting
+            .engine
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .evaluate(snapshot);
+        for transition in &transitions {
+            for notifier in &alerting.notifiers {
+                if let Err(e) = notifier.notify(transition) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+        }
+    }
+
+    // write a collection as a unit, logging whatever failed on the way
+    fn publish(&self, mut snapshot: Snapshot) -> usize {
+        self.alert(&snapshot);
+        let mut failures = 0;
+        let read_failures = std::mem::take(&mut snapshot.failures);
+        let results = read_failures
+            .into_iter()
+            .map(Err)
+            .chain([self.writer.write_batch(&snapshot), self.writer.flush()]);
+        for result in results {
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                failures += e.count();
+            }
+        }
+        failures
+    }
+
+    /// Collects `categories` every `interval` until a message arrives on
+    /// `shutdown` (or its sender is dropped), then closes the writer.
+    pub fn run(&self, categories: &[Category], interval: Duration, shutdown: &Receiver<()>) {
+        let sampling = self.sample_interval.filter(|sample| *sample < interval);
+        let mut window = WindowAggregator::new();
+        let mut deadline = Instant::now();
+        loop {
+            let wait = match sampling {
+                None => {
+                    self.process_all(categories);
+                    interval
+                }
+                Some(sample) => {
+                    window.add(self.reader.collect(categories));
+                    if Instant::now() >= deadline {
+                        if let Some(snapshot) = window.take() {
+                            self.report(snapshot);
+                        }
+                        deadline += interval;
+                    }
+                    sample.min(deadline.saturating_duration_since(Instant::now()))
+                }
+            };
+            match shutdown.recv_timeout(wait) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.close();
+    }
+
+    // release the writer resources
+    pub fn close(&self) {
+        self.writer.close();
     }
 }
 
@@ -28,11 +156,238 @@ where
     R: MetricReader,
     W: MetricWriter,
 {
-    // read and write metric for a category (disk, cpu, ...)
-    fn process_metrics(&self, category: Category) {
-        self.writer.write(self.reader.get_percent(&category));
-        //if category != Category::Cpu {
-        //     self.writer.write(self.reader.get_used(&category));
-        // }
+    // read and write metric for a category (disk, cpu, ...); a failed
+    // reading does not prevent the others from being reported
+    fn process_metrics(&self, category: Category) -> usize {
+        self.publish(self.reader.collect(&[category]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::alerts::models::{AlertRule, AlertTransition};
+    use crate::domain::metrics::models::Metric;
+    use crate::domain::ports::MetricError;
+    use crate::outbound::metric_reader::DummyMetricReader;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[derive(Default)]
+    struct RecordingWriter {
+        metrics: RefCell<Vec<Metric>>,
+        flushes: Cell<usize>,
+        closed: Cell<bool>,
+    }
+
+    impl MetricWriter for RecordingWriter {
+        fn write(&self, metric: Metric) -> Result<(), MetricError> {
+            self.metrics.borrow_mut().push(metric);
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<(), MetricError> {
+            self.flushes.set(self.flushes.get() + 1);
+            Ok(())
+        }
+
+        fn close(&self) {
+            self.closed.set(true);
+        }
+    }
+
+    #[test]
+    fn test_process_all() {
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default());
+        assert_eq!(service.process_all(&[Category::Disk, Category::Cpu]), 0);
+
+        let metrics = service.writer.metrics.borrow();
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(metrics[0].to_string(), "tux-Disk: 25%");
+        assert_eq!(metrics[1].to_string(), "tux-Disk: 25/100");
+        assert_eq!(metrics[2].to_string(), "tux-CPU: 25%");
+    }
+
+    struct FilesystemReader;
+
+    impl MetricReader for FilesystemReader {
+        fn get_percent(&self, category: &Category) -> Result<Metric, MetricError> {
+            DummyMetricReader.get_percent(category)
+        }
+
+        fn get_used(&self, category: &Category) -> Result<Metric, MetricError> {
+            match category {
+                Category::Filesystem(mount) if mount == "/gone" => {
+                    Err(MetricError::Unavailable(category.clone()))
+                }
+                _ => DummyMetricReader.get_used(category),
+            }
+        }
+
+        fn expand(&self, category: &Category) -> Vec<Category> {
+            match category {
+                Category::Disk => vec![
+                    Category::Disk,
+                    Category::Filesystem("/".to_string()),
+                    Category::Filesystem("/home".to_string()),
+                ],
+                _ => vec![category.clone()],
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_expanded_categories() {
+        let service = MetricService::new(FilesystemReader, RecordingWriter::default());
+        service.process_all(&[Category::Disk]);
+
+        let metrics: Vec<String> = service
+            .writer
+            .metrics
+            .borrow()
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![
+                "tux-Disk: 25%",
+                "tux-Disk: 25/100",
+                "tux-Filesystem /: 25%",
+                "tux-Filesystem /: 25/100",
+                "tux-Filesystem /home: 25%",
+                "tux-Filesystem /home: 25/100",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_reading_does_not_stop_collection() {
+        let service = MetricService::new(FilesystemReader, RecordingWriter::default());
+        assert_eq!(
+            service.process_metrics(Category::Filesystem("/gone".to_string())),
+            1
+        );
+        assert_eq!(service.process_all(&[Category::Disk, Category::Memory]), 0);
+
+        let metrics = service.writer.metrics.borrow();
+        assert_eq!(metrics[0].to_string(), "tux-Filesystem /gone: 25%");
+        assert_eq!(metrics.len(), 9);
+    }
+
+    struct FailingWriter;
+
+    impl MetricWriter for FailingWriter {
+        fn write(&self, metric: Metric) -> Result<(), MetricError> {
+            Err(MetricError::Write {
+                metric: metric.to_string(),
+                reason: "backend down".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_failed_writes_are_counted() {
+        let service = MetricService::new(DummyMetricReader, FailingWriter);
+        assert_eq!(service.process_all(&[Category::Cpu, Category::Memory]), 3);
+    }
+
+    #[test]
+    fn test_collection_is_flushed() {
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default());
+        service.process_all(&[Category::Disk, Category::Memory]);
+        service.process_all(&[Category::Cpu]);
+
+        assert_eq!(service.writer.metrics.borrow().len(), 5);
+        assert_eq!(service.writer.flushes.get(), 2);
+    }
+
+    #[derive(Default)]
+    struct RecordingNotifier {
+        transitions: Rc<RefCell<Vec<AlertTransition>>>,
+    }
+
+    impl AlertNotifier for RecordingNotifier {
+        fn notify(&self, transition: &AlertTransition) -> Result<(), MetricError> {
+            self.transitions.borrow_mut().push(transition.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_alerts() {
+        let engine = AlertEngine::new(vec![AlertRule {
+            name: "swap_used".to_string(),
+            category: Category::Swap,
+            warn: Some(20),
+            crit: None,
+            duration: Duration::ZERO,
+            hysteresis: 0,
+        }]);
+        let notifier = RecordingNotifier::default();
+        let transitions = Rc::clone(&notifier.transitions);
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default())
+            .with_alerts(engine, vec![Box::new(notifier)]);
+
+        service.process_all(&[Category::Swap, Category::Memory]);
+        service.process_all(&[Category::Swap]);
+
+        let transitions = transitions.borrow();
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].to_string(), "tux-Swap swap_used: WARN (25%)");
+    }
+
+    #[test]
+    fn test_run_stops_on_shutdown() {
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default());
+        let (tx, rx) = mpsc::channel();
+        tx.send(()).unwrap();
+
+        service.run(&[Category::Cpu], Duration::from_secs(3600), &rx);
+
+        assert_eq!(service.writer.metrics.borrow().len(), 1);
+        assert!(service.writer.closed.get());
+    }
+
+    #[test]
+    fn test_run_with_sampling() {
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default())
+            .with_sampling(Duration::from_millis(20));
+        let (tx, rx) = mpsc::channel();
+        let stop = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(250));
+            tx.send(()).unwrap();
+        });
+
+        service.run(&[Category::Cpu], Duration::from_millis(100), &rx);
+        stop.join().unwrap();
+
+        // One window right away, then one per interval
+        let windows: Vec<usize> = service
+            .writer
+            .metrics
+            .borrow()
+            .iter()
+            .filter_map(|metric| match metric {
+                Metric::Window(_, Category::Cpu, window) => Some(window.samples),
+                _ => None,
+            })
+            .collect();
+        assert!(windows.len() >= 2, "{:?}", windows);
+        assert_eq!(windows[0], 1);
+        assert!(windows[1] > 1, "{:?}", windows);
+    }
+
+    #[test]
+    fn test_run_stops_when_sender_dropped() {
+        let service = MetricService::new(DummyMetricReader, RecordingWriter::default());
+        let (tx, rx) = mpsc::channel::<()>();
+        drop(tx);
+
+        service.run(&[Category::Swap], Duration::from_secs(3600), &rx);
+
+        assert!(service.writer.closed.get());
     }
 }
@@ -1,9 +1,10 @@
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Represents different metrics that can be tracked.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Metric {
     /// A percentage-based metric (0-100) for a specific category.
     ///
@@ -20,15 +21,497 @@ pub enum Metric {
     /// * `used`: The amount of the resource being used (e.g., 1000 MB).
     /// * `total`: The total amount of the resource available (e.g., 4000 MB).
     Used(String, Category, u64, u64),
+    /// Statistics of the percentage samples of a category taken over a
+    /// publish window.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the metric (e.g., "CPU Usage").
+    /// * `category`: The category of the metric (e.g., "Cpu").
+    /// * `window`: The minimum, maximum, mean and 95th percentile.
+    Window(String, Category, Window),
+    /// Any other measured quantity of a category, such as a load average.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the metric (e.g., "Load average").
+    /// * `category`: The category of the metric (e.g., "Load").
+    /// * `quantity`: What is measured (e.g., the 1-minute load average).
+    /// * `value`: The measured value, in the unit of the quantity.
+    Value(String, Category, Quantity, f64),
+    /// The processes using the most of a resource, most demanding first.
+    ///
+    /// # Parameters
+    /// * `name`: The name of the metric (e.g., "Top processes").
+    /// * `category`: The resource the processes are ranked by (`Cpu` or `Memory`).
+    /// * `processes`: The top processes, with their CPU and memory usage.
+    Top(String, Category, Vec<ProcessUsage>),
+}
+
+/// Resource usage of a single process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessUsage {
+    pub name: String,
+    pub pid: u32,
+    /// CPU usage, in percent of one core (above 100 when using several).
+    pub cpu: f32,
+    /// Resident memory, in bytes.
+    pub memory: u64,
+}
+
+impl ProcessUsage {
+    /// The `count` processes using the most of `category` (`Cpu` or
+    /// `Memory`), most demanding first, ties broken by pid.
+    pub fn top(
+        mut processes: Vec<ProcessUsage>,
+        category: &Category,
+        count: usize,
+    ) -> Vec<ProcessUsage> {
+        processes.sort_by(|a, b| {
+            let order = match category {
+                Category::Memory => b.memory.cmp(&a.memory),
+                _ => b.cpu.total_cmp(&a.cpu),
+            };
+            order.then(a.pid.cmp(&b.pid))
+        });
+        processes.truncate(count);
+        processes
+    }
+}
+
+/// A measured quantity other than a usage, with its unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Quantity {
+    /// Load average over 1 minute.
+    Load1,
+    /// Load average over 5 minutes.
+    Load5,
+    /// Load average over 15 minutes.
+    Load15,
+    /// Time since boot, in seconds.
+    Uptime,
+    /// Boot time, in seconds since the Unix epoch.
+    BootTime,
+    /// Bytes received per second.
+    RxBytes,
+    /// Bytes transmitted per second.
+    TxBytes,
+    /// Packets received per second.
+    RxPackets,
+    /// Packets transmitted per second.
+    TxPackets,
+    /// Receive errors since boot.
+    RxErrors,
+    /// Transmit errors since boot.
+    TxErrors,
+    /// Received packets dropped since boot.
+    RxDropped,
+    /// Transmitted packets dropped since boot.
+    TxDropped,
+    /// Current temperature, in degrees Celsius.
+    Temperature,
+    /// Highest temperature seen, in degrees Celsius.
+    TemperatureMax,
+    /// Critical temperature threshold, in degrees Celsius.
+    TemperatureCritical,
+    /// Whether a watched process is running: 1 if so, else 0.
+    Running,
+    /// Number of running instances of a watched process.
+    Instances,
+    /// Combined CPU usage of the instances, in percent of one core.
+    ProcessCpu,
+    /// Combined resident memory of the instances, in bytes.
+    ProcessMemory,
+    /// Memory used by a cgroup, in bytes.
+    MemoryUsed,
+    /// Memory limit of a cgroup, in bytes.
+    MemoryMax,
+    /// Memory used by a cgroup, in percent of its limit.
+    MemoryUsage,
+    /// CPU usage of a cgroup, in percent of one core.
+    CpuUsage,
+    /// CPU periods a cgroup was throttled in.
+    Throttled,
+    /// Bytes read per second by a cgroup.
+    IoRead,
+    /// Bytes written per second by a cgroup.
+    IoWrite,
+    /// Share of time some tasks stalled on a resource over 10 seconds, in percent.
+    SomeAvg10,
+    /// Share of time some tasks stalled on a resource over 60 seconds, in percent.
+    SomeAvg60,
+    /// Share of time some tasks stalled on a resource over 300 seconds, in percent.
+    SomeAvg300,
+    /// Share of time all tasks stalled on a resource over 10 seconds, in percent.
+    FullAvg10,
+    /// Share of time all tasks stalled on a resource over 60 seconds, in percent.
+    FullAvg60,
+    /// Share of time all tasks stalled on a resource over 300 seconds, in percent.
+    FullAvg300,
+}
+
+impl Quantity {
+    /// Lowercase name, used as field and sensor id, e.g. `load1`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantity::Load1 => "load1",
+            Quantity::Load5 => "load5",
+            Quantity::Load15 => "load15",
+            Quantity::Uptime => "uptime",
+            Quantity::BootTime => "boot_time",
+            Quantity::RxBytes => "rx_bytes",
+            Quantity::TxBytes => "tx_bytes",
+            Quantity::RxPackets => "rx_packets",
+            Quantity::TxPackets => "tx_packets",
+            Quantity::RxErrors => "rx_errors",
+            Quantity::TxErrors => "tx_errors",
+            Quantity::RxDropped => "rx_dropped",
+            Quantity::TxDropped => "tx_dropped",
+            Quantity::Temperature => "temperature",
+            Quantity::TemperatureMax => "temperature_max",
+            Quantity::TemperatureCritical => "temperature_critical",
+            Quantity::Running => "running",
+            Quantity::Instances => "instances",
+            Quantity::ProcessCpu => "process_cpu",
+            Quantity::ProcessMemory => "process_memory",
+            Quantity::MemoryUsed => "memory_used",
+            Quantity::MemoryMax => "memory_max",
+            Quantity::MemoryUsage => "memory_usage",
+            Quantity::CpuUsage => "cpu_usage",
+            Quantity::Throttled => "throttled",
+            Quantity::IoRead => "io_read_bytes",
+            Quantity::IoWrite => "io_write_bytes",
+            Quantity::SomeAvg10 => "some_avg10",
+            Quantity::SomeAvg60 => "some_avg60",
+            Quantity::SomeAvg300 => "some_avg300",
+            Quantity::FullAvg10 => "full_avg10",
+            Quantity::FullAvg60 => "full_avg60",
+            Quantity::FullAvg300 => "full_avg300",
+        }
+    }
+
+    /// Unit of the values, if any.
+    pub fn unit(self) -> Option<&'static str> {
+        match self {
+            Quantity::Uptime | Quantity::BootTime => Some("s"),
+            Quantity::RxBytes | Quantity::TxBytes | Quantity::IoRead | Quantity::IoWrite => {
+                Some("B/s")
+            }
+            Quantity::RxPackets | Quantity::TxPackets => Some("packets/s"),
+            Quantity::Temperature | Quantity::TemperatureMax | Quantity::TemperatureCritical => {
+                Some("°C")
+            }
+            Quantity::ProcessCpu
+            | Quantity::MemoryUsage
+            | Quantity::CpuUsage
+            | Quantity::SomeAvg10
+            | Quantity::SomeAvg60
+            | Quantity::SomeAvg300
+            | Quantity::FullAvg10
+            | Quantity::FullAvg60
+            | Quantity::FullAvg300 => Some("%"),
+            Quantity::ProcessMemory | Quantity::MemoryUsed | Quantity::MemoryMax => Some("B"),
+            Quantity::Load1
+            | Quantity::Load5
+            | Quantity::Load15
+            | Quantity::RxErrors
+            | Quantity::TxErrors
+            | Quantity::RxDropped
+            | Quantity::TxDropped
+            | Quantity::Running
+            | Quantity::Instances
+            | Quantity::Throttled => None,
+        }
+    }
+
+    /// Whether the values only ever increase (until the next boot).
+    pub fn is_counter(self) -> bool {
+        matches!(
+            self,
+            Quantity::RxErrors
+                | Quantity::TxErrors
+                | Quantity::RxDropped
+                | Quantity::TxDropped
+                | Quantity::Throttled
+        )
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Quantity::Load1 => "Load average over 1 minute.",
+            Quantity::Load5 => "Load average over 5 minutes.",
+            Quantity::Load15 => "Load average over 15 minutes.",
+            Quantity::Uptime => "Time since boot, in seconds.",
+            Quantity::BootTime => "Boot time, in seconds since the Unix epoch.",
+            Quantity::RxBytes => "Bytes received per second.",
+            Quantity::TxBytes => "Bytes transmitted per second.",
+            Quantity::RxPackets => "Packets received per second.",
+            Quantity::TxPackets => "Packets transmitted per second.",
+            Quantity::RxErrors => "Receive errors since boot.",
+            Quantity::TxErrors => "Transmit errors since boot.",
+            Quantity::RxDropped => "Received packets dropped since boot.",
+            Quantity::TxDropped => "Transmitted packets dropped since boot.",
+            Quantity::Temperature => "Current temperature, in degrees Celsius.",
+            Quantity::TemperatureMax => "Highest temperature seen, in degrees Celsius.",
+            Quantity::TemperatureCritical => "Critical temperature threshold, in degrees Celsius.",
+            Quantity::Running => "Whether a watched process is running.",
+            Quantity::Instances => "Running instances of a watched process.",
+            Quantity::ProcessCpu => "CPU usage of a watched process, in percent of one core.",
+            Quantity::ProcessMemory => "Resident memory of a watched process, in bytes.",
+            Quantity::MemoryUsed => "Memory used by a cgroup, in bytes.",
+            Quantity::MemoryMax => "Memory limit of a cgroup, in bytes.",
+            Quantity::MemoryUsage => "Memory used by a cgroup, in percent of its limit.",
+            Quantity::CpuUsage => "CPU usage of a cgroup, in percent of one core.",
+            Quantity::Throttled => "CPU periods a cgroup was throttled in.",
+            Quantity::IoRead => "Bytes read per second by a cgroup.",
+            Quantity::IoWrite => "Bytes written per second by a cgroup.",
+            Quantity::SomeAvg10 => {
+                "Share of time some tasks stalled on the resource over 10 seconds, in percent."
+            }
+            Quantity::SomeAvg60 => {
+                "Share of time some tasks stalled on the resource over 60 seconds, in percent."
+            }
+            Quantity::SomeAvg300 => {
+                "Share of time some tasks stalled on the resource over 300 seconds, in percent."
+            }
+            Quantity::FullAvg10 => {
+                "Share of time all tasks stalled on the resource over 10 seconds, in percent."
+            }
+            Quantity::FullAvg60 => {
+                "Share of time all tasks stalled on the resource over 60 seconds, in percent."
+            }
+            Quantity::FullAvg300 => {
+                "Share of time all tasks stalled on the resource over 300 seconds, in percent."
+            }
+        }
+    }
+}
+
+/// Statistics of the percentage samples taken over a publish window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub min: Percentage,
+    pub max: Percentage,
+    /// Mean of the samples, rounded.
+    pub mean: Percentage,
+    /// 95th percentile of the samples (nearest rank).
+    pub p95: Percentage,
+    /// Number of samples taken.
+    pub samples: usize,
+}
+
+impl Window {
+    /// Summarizes `samples`, or gives `None` when there are none.
+    pub fn from_samples(samples: &[Percentage]) -> Option<Window> {
+        let mut values: Vec<u8> = samples.iter().map(|Percentage(value)| *value).collect();
+        values.sort_unstable();
+        let min = *values.first()?;
+        let max = *values.last()?;
+        let sum: u64 = values.iter().map(|value| u64::from(*value)).sum();
+        let mean = (sum as f64 / values.len() as f64).round() as u8;
+        let rank = (values.len() * 95).div_ceil(100);
+        let p95 = values[rank.max(1) - 1];
+        Some(Window {
+            min: Percentage(min),
+            max: Percentage(max),
+            mean: Percentage(mean),
+            p95: Percentage(p95),
+            samples: values.len(),
+        })
+    }
 }
 
 /// Represents the different categories of resources that can be measured.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Category {
+    /// All the reported filesystems together.
     Disk,
     Memory,
     Cpu,
     Swap,
+    /// A single filesystem, identified by its mount point.
+    #[serde(skip)]
+    Filesystem(String),
+    /// A single CPU core, identified by its index.
+    #[serde(skip)]
+    Core(usize),
+    /// The 1, 5 and 15-minute load averages.
+    Load,
+    /// Time since boot, and boot time.
+    Uptime,
+    /// Every reported network interface.
+    Network,
+    /// A single network interface, identified by its name.
+    #[serde(skip)]
+    Interface(String),
+    /// Every temperature sensor.
+    Temperature,
+    /// A single temperature sensor, identified by its label.
+    #[serde(skip)]
+    Sensor(String),
+    /// The processes using the most CPU and memory.
+    Processes,
+    /// Every watched process.
+    Watch,
+    /// A single watched process, identified by the name of its rule.
+    #[serde(skip)]
+    Watched(String),
+    /// Every selected cgroup.
+    Cgroups,
+    /// A single cgroup, identified by its path below the cgroup root.
+    #[serde(skip)]
+    Cgroup(String),
+    /// Pressure stall information of every resource.
+    Pressure,
+    /// Pressure stall information of a single resource (`cpu`, `memory` or
+    /// `io`).
+    #[serde(skip)]
+    Psi(String),
+}
+
+impl Category {
+    /// Whether this is a CPU category, which has no used/total counterpart.
+    pub fn is_cpu(&self) -> bool {
+        matches!(self, Category::Cpu | Category::Core(_))
+    }
+
+    /// Whether the category reports a usage percentage (and, unless CPU, a
+    /// used/total pair), rather than other quantities.
+    pub fn is_usage(&self) -> bool {
+        !matches!(
+            self,
+            Category::Load
+                | Category::Uptime
+                | Category::Network
+                | Category::Interface(_)
+                | Category::Temperature
+                | Category::Sensor(_)
+                | Category::Processes
+                | Category::Watch
+                | Category::Watched(_)
+                | Category::Cgroups
+                | Category::Cgroup(_)
+                | Category::Pressure
+                | Category::Psi(_)
+        )
+    }
+
+    /// Lowercase name of the kind of resource, without the mount point or
+    /// core index, e.g. `filesystem`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Category::Disk => "disk",
+            Category::Memory => "memory",
+            Category::Cpu => "cpu",
+            Category::Swap => "swap",
+            Category::Filesystem(_) => "filesystem",
+            Category::Core(_) => "core",
+            Category::Load => "load",
+            Category::Uptime => "uptime",
+            Category::Network => "network",
+            Category::Interface(_) => "interface",
+            Category::Temperature => "temperature",
+            Category::Sensor(_) => "sensor",
+            Category::Processes => "processes",
+            Category::Watch => "watch",
+            Category::Watched(_) => "watched",
+            Category::Cgroups => "cgroups",
+            Category::Cgroup(_) => "cgroup",
+            Category::Pressure => "pressure",
+            Category::Psi(_) => "psi",
+        }
+    }
+
+    /// Stable id fragment of the category, distinct for every category, e.g.
+    /// `disk_mnt_data`, `cpu_core2` or `net_eth0`.
+    pub fn slug(&self) -> String {
+        match self {
+            Category::Filesystem(mount) => format!("disk_{}", mount_slug(mount)),
+            Category::Core(index) => format!("cpu_core{}", index),
+            Category::Interface(name) => format!("net_{}", slug(name)),
+            Category::Sensor(label) => format!("temp_{}", slug(label)),
+            Category::Watched(name) => format!("watch_{}", slug(name)),
+            Category::Cgroup(path) => format!("cgroup_{}", slug(path)),
+            Category::Psi(resource) => format!("psi_{}", slug(resource)),
+            _ => self.kind().to_string(),
+        }
+    }
+
+    /// Readable fragment of entity names, e.g. `disk_mnt_data` or
+    /// `temp_coretemp_core_0`. Unlike the slug, it may be shared by two
+    /// categories.
+    pub fn label(&self) -> String {
+        match self {
+            Category::Filesystem(mount) => format!("disk_{}", readable(mount)),
+            Category::Interface(name) => format!("net_{}", readable(name)),
+            Category::Sensor(label) => format!("temp_{}", readable(label)),
+            Category::Watched(name) => format!("watch_{}", readable(name)),
+            Category::Cgroup(path) => format!("cgroup_{}", readable(path)),
+            Category::Psi(resource) => format!("psi_{}", readable(resource)),
+            _ => self.slug(),
+        }
+    }
+
+    /// The category selecting this one in the configuration, e.g. `Disk` for
+    /// a single filesystem.
+    pub fn selector(&self) -> Category {
+        match self {
+            Category::Filesystem(_) => Category::Disk,
+            Category::Core(_) => Category::Cpu,
+            Category::Interface(_) => Category::Network,
+            Category::Sensor(_) => Category::Temperature,
+            Category::Watched(_) => Category::Watch,
+            Category::Cgroup(_) => Category::Cgroups,
+            Category::Psi(_) => Category::Pressure,
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Turns a mount point into a stable sensor id fragment, distinct for every
+/// mount point: `/` gives `root` (and `/root` gives `root-`), `/mnt/data`
+/// gives `mnt_data`.
+pub fn mount_slug(mount: &str) -> String {
+    match mount.strip_prefix('/').unwrap_or(mount) {
+        "" => "root".to_string(),
+        "root" => "root-".to_string(),
+        path => slug(path),
+    }
+}
+
+/// Lowercases a name, replacing anything but letters and digits with `_`:
+/// `/` gives `root`, `/mnt/Data-1` gives `mnt_data_1`.
+pub fn readable(name: &str) -> String {
+    let readable: String = name
+        .trim_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if readable.is_empty() {
+        "root".to_string()
+    } else {
+        readable
+    }
+}
+
+/// Turns a name into a stable id fragment of `[a-z0-9_-]`, distinct for
+/// distinct names: `/` gives `_`, and any byte other than a lowercase letter
+/// or digit gives `-` and its hex code, e.g. `eth0.100` gives `eth0-2e100`.
+pub fn slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' => slug.push(byte as char),
+            b'/' => slug.push('_'),
+            _ => slug.push_str(&format!("-{:02x}", byte)),
+        }
+    }
+    slug
 }
 
 impl fmt::Display for Metric {
@@ -40,6 +523,27 @@ impl fmt::Display for Metric {
             Metric::Used(host, category, used, total) => {
                 write!(f, "{}-{}: {}/{}", host, category, used, total)
             }
+            Metric::Window(host, category, window) => write!(
+                f,
+                "{}-{}: {}-{}% (mean {}%, p95 {}%)",
+                host, category, window.min.0, window.max.0, window.mean.0, window.p95.0
+            ),
+            Metric::Value(host, category, quantity, value) => write!(
+                f,
+                "{}-{} {}: {}{}",
+                host,
+                category,
+                quantity.name(),
+                value,
+                quantity.unit().unwrap_or_default()
+            ),
+            Metric::Top(host, category, processes) => {
+                let names: Vec<String> = processes
+                    .iter()
+                    .map(|process| format!("{} ({})", process.name, process.pid))
+                    .collect();
+                write!(f, "{}-{} top: {}", host, category, names.join(", "))
+            }
         }
     }
 }
@@ -51,6 +555,21 @@ impl fmt::Display for Category {
             Category::Memory => write!(f, "Memory"),
             Category::Cpu => write!(f, "CPU"),
             Category::Swap => write!(f, "Swap"),
+            Category::Filesystem(mount) => write!(f, "Filesystem {}", mount),
+            Category::Core(index) => write!(f, "CPU core {}", index),
+            Category::Load => write!(f, "Load"),
+            Category::Uptime => write!(f, "Uptime"),
+            Category::Network => write!(f, "Network"),
+            Category::Interface(name) => write!(f, "Interface {}", name),
+            Category::Temperature => write!(f, "Temperature"),
+            Category::Sensor(label) => write!(f, "Sensor {}", label),
+            Category::Processes => write!(f, "Processes"),
+            Category::Watch => write!(f, "Watch"),
+            Category::Watched(name) => write!(f, "Watched {}", name),
+            Category::Cgroups => write!(f, "Cgroups"),
+            Category::Cgroup(path) => write!(f, "Cgroup {}", path),
+            Category::Pressure => write!(f, "Pressure"),
+            Category::Psi(resource) => write!(f, "Pressure {}", resource),
         }
     }
 }
@@ -110,12 +629,147 @@ mod tests {
         assert_eq!(metric.to_string(), "test-Memory: 4096/8192");
     }
 
+    #[test]
+    fn test_window_from_samples() {
+        let samples: Vec<Percentage> = [40, 10, 100, 20, 30].into_iter().map(Percentage).collect();
+        let window = Window::from_samples(&samples).unwrap();
+        assert_eq!(
+            window,
+            Window {
+                min: Percentage(10),
+                max: Percentage(100),
+                mean: Percentage(40),
+                p95: Percentage(100),
+                samples: 5,
+            }
+        );
+        let metric = Metric::Window(host(), Category::Cpu, window);
+        assert_eq!(metric.to_string(), "test-CPU: 10-100% (mean 40%, p95 100%)");
+
+        let samples: Vec<Percentage> = (1..=100).map(Percentage).collect();
+        assert_eq!(Window::from_samples(&samples).unwrap().p95, Percentage(95));
+        assert_eq!(Window::from_samples(&[]), None);
+    }
+
+    #[test]
+    fn test_metric_value_display() {
+        let metric = Metric::Value(host(), Category::Load, Quantity::Load5, 0.75);
+        assert_eq!(metric.to_string(), "test-Load load5: 0.75");
+        let metric = Metric::Value(host(), Category::Uptime, Quantity::Uptime, 3600.0);
+        assert_eq!(metric.to_string(), "test-Uptime uptime: 3600s");
+    }
+
+    #[test]
+    fn test_process_top() {
+        let process = |name: &str, pid, cpu, memory| ProcessUsage {
+            name: name.to_string(),
+            pid,
+            cpu,
+            memory,
+        };
+        let processes = vec![
+            process("postgres", 42, 12.5, 4096),
+            process("bash", 7, 0.0, 1024),
+            process("cargo", 99, 180.0, 2048),
+            process("sshd", 3, 0.0, 1024),
+        ];
+        let names = |top: Vec<ProcessUsage>| -> Vec<u32> { top.iter().map(|p| p.pid).collect() };
+        assert_eq!(
+            names(ProcessUsage::top(processes.clone(), &Category::Cpu, 3)),
+            vec![99, 42, 3]
+        );
+        assert_eq!(
+            names(ProcessUsage::top(processes.clone(), &Category::Memory, 10)),
+            vec![42, 99, 3, 7]
+        );
+
+        let top = ProcessUsage::top(processes, &Category::Cpu, 2);
+        let metric = Metric::Top(host(), Category::Cpu, top);
+        assert_eq!(
+            metric.to_string(),
+            "test-CPU top: cargo (99), postgres (42)"
+        );
+        assert!(!Category::Processes.is_usage());
+    }
+
     #[test]
     fn test_category_display() {
         assert_eq!(Category::Cpu.to_string(), "CPU");
         assert_eq!(Category::Memory.to_string(), "Memory");
         assert_eq!(Category::Disk.to_string(), "Disk");
         assert_eq!(Category::Swap.to_string(), "Swap");
+        assert_eq!(
+            Category::Filesystem("/home".to_string()).to_string(),
+            "Filesystem /home"
+        );
+        assert_eq!(Category::Core(3).to_string(), "CPU core 3");
+        assert_eq!(Category::Load.to_string(), "Load");
+    }
+
+    #[test]
+    fn test_category_kind() {
+        assert_eq!(Category::Cpu.kind(), "cpu");
+        assert_eq!(Category::Filesystem("/".to_string()).kind(), "filesystem");
+        assert_eq!(Category::Core(0).kind(), "core");
+        assert_eq!(Category::Uptime.kind(), "uptime");
+    }
+
+    #[test]
+    fn test_mount_slug() {
+        assert_eq!(mount_slug("/"), "root");
+        assert_eq!(mount_slug("/root"), "root-");
+        assert_eq!(mount_slug("/home"), "home");
+        assert_eq!(mount_slug("/mnt/Data-1/"), "mnt_-44ata-2d1_");
+        assert_ne!(mount_slug("/mnt/a-b"), mount_slug("/mnt/a_b"));
+        assert_ne!(mount_slug("/mnt/a b"), mount_slug("/mnt/a_b"));
+    }
+
+    #[test]
+    fn test_readable() {
+        assert_eq!(readable("/"), "root");
+        assert_eq!(readable("/mnt/Data-1/"), "mnt_data_1");
+        assert_eq!(
+            Category::Sensor("coretemp Core 0".to_string()).label(),
+            "temp_coretemp_core_0"
+        );
+        assert_eq!(Category::Core(1).label(), "cpu_core1");
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("eth0"), "eth0");
+        assert_eq!(slug("enp3s0.100"), "enp3s0-2e100");
+        assert_eq!(slug("a/b"), "a_b");
+        assert_eq!(slug("a_b"), "a-5fb");
+        assert_eq!(slug("Data"), "-44ata");
+        assert_ne!(slug("Data"), slug("data"));
+        assert_eq!(slug("café"), "caf-c3-a9");
+    }
+
+    #[test]
+    fn test_category_slug() {
+        assert_eq!(Category::Swap.slug(), "swap");
+        assert_eq!(Category::Filesystem("/".to_string()).slug(), "disk_root");
+        assert_eq!(Category::Core(2).slug(), "cpu_core2");
+        assert_eq!(Category::Core(2).selector(), Category::Cpu);
+        let interface = Category::Interface("enp3s0.100".to_string());
+        assert_eq!(interface.slug(), "net_enp3s0-2e100");
+        assert_eq!(interface.selector(), Category::Network);
+        assert!(!interface.is_usage());
+        let sensor = Category::Sensor("coretemp Package id 0".to_string());
+        assert_eq!(sensor.slug(), "temp_coretemp-20-50ackage-20id-200");
+        assert_eq!(sensor.selector(), Category::Temperature);
+        let watched = Category::Watched("Home Assistant".to_string());
+        assert_eq!(watched.slug(), "watch_-48ome-20-41ssistant");
+        assert_eq!(watched.selector(), Category::Watch);
+        assert!(!watched.is_usage());
+        let cgroup = Category::Cgroup("system.slice/docker-1a2b.scope".to_string());
+        assert_eq!(cgroup.slug(), "cgroup_system-2eslice_docker-2d1a2b-2escope");
+        assert_eq!(cgroup.selector(), Category::Cgroups);
+        let psi = Category::Psi("io".to_string());
+        assert_eq!(psi.slug(), "psi_io");
+        assert_eq!(psi.selector(), Category::Pressure);
+        assert_eq!(psi.to_string(), "Pressure io");
     }
 
     #[test]
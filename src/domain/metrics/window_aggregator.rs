@@ -0,0 +1,122 @@
+use crate::domain::metrics::models::{Category, Metric, Percentage, Window};
+use crate::domain::ports::Snapshot;
+use std::collections::HashMap;
+
+/// Accumulates the snapshots sampled during a publish window.
+///
+/// The window is published as its last snapshot, each percentage being
+/// followed by the statistics of all its samples. Readings that failed in the
+/// last sample are only reported as failures.
+#[derive(Debug, Default)]
+pub struct WindowAggregator {
+    samples: HashMap<(String, Category), Vec<Percentage>>,
+    latest: Option<Snapshot>,
+}
+
+impl WindowAggregator {
+    pub fn new() -> Self {
+        WindowAggregator::default()
+    }
+
+    /// Adds a sample to the window.
+    pub fn add(&mut self, snapshot: Snapshot) {
+        for metric in &snapshot.metrics {
+            if let Metric::Percent(host, category, value) = metric {
+                self.samples
+                    .entry((host.clone(), category.clone()))
+                    .or_default()
+                    .push(value.clone());
+            }
+        }
+        self.latest = Some(snapshot);
+    }
+
+    /// Closes the window, giving its snapshot (if anything was sampled) and
+    /// starting a new one.
+    pub fn take(&mut self) -> Option<Snapshot> {
+        let latest = self.latest.take()?;
+        let mut samples = std::mem::take(&mut self.samples);
+        let mut snapshot = Snapshot {
+            metrics: Vec::with_capacity(latest.metrics.len() * 2),
+            ..latest
+        };
+        for metric in latest.metrics {
+            let window = match &metric {
+                Metric::Percent(host, category, _) => samples
+                    .remove(&(host.clone(), category.clone()))
+                    .and_then(|values| Window::from_samples(&values))
+                    .map(|window| Metric::Window(host.clone(), category.clone(), window)),
+                _ => None,
+            };
+            snapshot.metrics.push(metric);
+            snapshot.metrics.extend(window);
+        }
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ports::MetricError;
+
+    fn sample(cpu: u8) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.push(Ok(Metric::Percent(
+            "tux".to_string(),
+            Category::Cpu,
+            Percentage(cpu),
+        )));
+        snapshot.push(Ok(Metric::Used("tux".to_string(), Category::Memory, 1, 2)));
+        snapshot
+    }
+
+    #[test]
+    fn test_window() {
+        let mut aggregator = WindowAggregator::new();
+        for cpu in [10, 90, 20] {
+            aggregator.add(sample(cpu));
+        }
+        let snapshot = aggregator.take().unwrap();
+        assert_eq!(
+            snapshot.metrics,
+            vec![
+                Metric::Percent("tux".to_string(), Category::Cpu, Percentage(20)),
+                Metric::Window(
+                    "tux".to_string(),
+                    Category::Cpu,
+                    Window {
+                        min: Percentage(10),
+                        max: Percentage(90),
+                        mean: Percentage(40),
+                        p95: Percentage(90),
+                        samples: 3,
+                    }
+                ),
+                Metric::Used("tux".to_string(), Category::Memory, 1, 2),
+            ]
+        );
+
+        // The next window starts empty
+        assert!(aggregator.take().is_none());
+        aggregator.add(sample(50));
+        let snapshot = aggregator.take().unwrap();
+        assert!(matches!(
+            &snapshot.metrics[1],
+            Metric::Window(_, _, Window { samples: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_failures_of_last_sample() {
+        let mut aggregator = WindowAggregator::new();
+        aggregator.add(sample(10));
+        let mut failed = Snapshot::new();
+        failed.push(Err(MetricError::Unavailable(Category::Cpu)));
+        aggregator.add(failed);
+
+        let snapshot = aggregator.take().unwrap();
+        assert!(snapshot.metrics.is_empty());
+        assert_eq!(snapshot.failures.len(), 1);
+    }
+}
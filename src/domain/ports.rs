@@ -1,14 +1,148 @@
-use crate::domain::metrics::models::{Category, Metric};
+use crate::domain::alerts::models::AlertTransition;
+use crate::domain::metrics::models::{Category, InvalidPercentage, Metric};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Errors raised while reading or writing a metric.
+#[derive(Debug, Error)]
+pub enum MetricError {
+    #[error("Unable to determine the host name")]
+    HostName,
+    #[error("{0} does not report this kind of value")]
+    Unsupported(Category),
+    #[error("{0} is no longer available")]
+    Unavailable(Category),
+    #[error("Invalid percentage for {category}: {source}")]
+    InvalidPercentage {
+        category: Category,
+        source: InvalidPercentage,
+    },
+    #[error("Unable to write {metric}: {reason}")]
+    Write { metric: String, reason: String },
+    #[error("Unable to notify {transition}: {reason}")]
+    Notify { transition: String, reason: String },
+    #[error("{failed} of {total} metric(s) could not be written, first error: {first}")]
+    Batch {
+        failed: usize,
+        total: usize,
+        first: Box<MetricError>,
+    },
+}
+
+impl MetricError {
+    /// Number of metrics lost because of this error.
+    pub fn count(&self) -> usize {
+        match self {
+            MetricError::Batch { failed, .. } => *failed,
+            _ => 1,
+        }
+    }
+}
+
+/// Metrics collected in a single pass, with the readings that failed.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub timestamp: SystemTime,
+    pub metrics: Vec<Metric>,
+    pub failures: Vec<MetricError>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Snapshot {
+            timestamp: SystemTime::now(),
+            metrics: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, reading: Result<Metric, MetricError>) {
+        match reading {
+            Ok(metric) => self.metrics.push(metric),
+            Err(e) => self.failures.push(e),
+        }
+    }
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Snapshot::new()
+    }
+}
 
 pub trait MetricProcessor {
-    fn process_metrics(&self, category: Category);
+    /// Returns the number of metrics that could not be read or written.
+    fn process_metrics(&self, category: Category) -> usize;
 }
 
 pub trait MetricReader {
-    fn get_percent(&self, category: &Category) -> Metric;
-    fn get_used(&self, category: &Category) -> Metric;
+    fn get_percent(&self, category: &Category) -> Result<Metric, MetricError>;
+    fn get_used(&self, category: &Category) -> Result<Metric, MetricError>;
+
+    /// Reads the quantities of a category that does not report a usage, e.g.
+    /// the three load averages.
+    fn get_values(&self, category: &Category) -> Result<Vec<Metric>, MetricError> {
+        Err(MetricError::Unsupported(category.clone()))
+    }
+
+    /// Lists the categories reported when `category` is collected, e.g. the
+    /// disk total followed by every mounted filesystem.
+    fn expand(&self, category: &Category) -> Vec<Category> {
+        vec![category.clone()]
+    }
+
+    /// Reads every metric of `categories` in one pass.
+    fn collect(&self, categories: &[Category]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for category in categories {
+            for category in self.expand(category) {
+                if !category.is_usage() {
+                    match self.get_values(&category) {
+                        Ok(metrics) => snapshot.metrics.extend(metrics),
+                        Err(e) => snapshot.failures.push(e),
+                    }
+                    continue;
+                }
+                snapshot.push(self.get_percent(&category));
+                // CPU usage has no used/total counterpart
+                if !category.is_cpu() {
+                    snapshot.push(self.get_used(&category));
+                }
+            }
+        }
+        snapshot
+    }
 }
 
 pub trait MetricWriter {
-    fn write(&self, metric: Metric);
+    fn write(&self, metric: Metric) -> Result<(), MetricError>;
+
+    /// Writes every metric of a collection, going on past failures.
+    fn write_batch(&self, snapshot: &Snapshot) -> Result<(), MetricError> {
+        let mut errors = snapshot
+            .metrics
+            .iter()
+            .filter_map(|metric| self.write(metric.clone()).err());
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        Err(MetricError::Batch {
+            failed: 1 + errors.count(),
+            total: snapshot.metrics.len(),
+            first: Box::new(first),
+        })
+    }
+
+    /// Sends whatever the writer buffered, at the end of every collection.
+    fn flush(&self) -> Result<(), MetricError> {
+        Ok(())
+    }
+
+    /// Releases any resources held by the writer (e.g. broker connections).
+    fn close(&self) {}
+}
+
+pub trait AlertNotifier {
+    /// Reports a change of level of an alert rule.
+    fn notify(&self, transition: &AlertTransition) -> Result<(), MetricError>;
 }
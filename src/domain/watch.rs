@@ -0,0 +1,85 @@
+use regex::Regex;
+use std::path::Path;
+
+/// Selects the processes of a watched program or service.
+#[derive(Debug, Clone)]
+pub enum ProcessMatcher {
+    /// Process name, exactly (e.g. `nginx`).
+    Name(String),
+    /// Full path of the executable (e.g. `/usr/sbin/nginx`).
+    Exe(String),
+    /// Regular expression searched in the command line, arguments separated
+    /// by spaces.
+    Cmdline(Regex),
+}
+
+impl ProcessMatcher {
+    pub fn matches(&self, name: &str, exe: Option<&Path>, cmdline: &str) -> bool {
+        match self {
+            ProcessMatcher::Name(expected) => name == expected,
+            ProcessMatcher::Exe(expected) => exe.is_some_and(|exe| exe == Path::new(expected)),
+            ProcessMatcher::Cmdline(regex) => regex.is_match(cmdline),
+        }
+    }
+}
+
+/// Instances of a watched process found in a refresh, with their combined
+/// usage.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WatchedUsage {
+    pub instances: usize,
+    /// CPU usage, in percent of one core.
+    pub cpu: f32,
+    /// Resident memory, in bytes.
+    pub memory: u64,
+}
+
+impl WatchedUsage {
+    pub fn add(&mut self, cpu: f32, memory: u64) {
+        self.instances += 1;
+        self.cpu += cpu;
+        self.memory += memory;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        let matcher = ProcessMatcher::Name("nginx".to_string());
+        assert!(matcher.matches("nginx", None, ""));
+        assert!(!matcher.matches("nginx-debug", None, "nginx"));
+    }
+
+    #[test]
+    fn test_exe() {
+        let matcher = ProcessMatcher::Exe("/usr/sbin/nginx".to_string());
+        assert!(matcher.matches("nginx", Some(Path::new("/usr/sbin/nginx")), ""));
+        assert!(!matcher.matches("nginx", Some(Path::new("/usr/bin/nginx")), ""));
+        assert!(!matcher.matches("nginx", None, "/usr/sbin/nginx"));
+    }
+
+    #[test]
+    fn test_cmdline() {
+        let matcher = ProcessMatcher::Cmdline(Regex::new(r"java .*-jar \S*app\.jar").unwrap());
+        assert!(matcher.matches("java", None, "/usr/bin/java -Xmx1g -jar /opt/app.jar"));
+        assert!(!matcher.matches("java", None, "/usr/bin/java -jar /opt/other.jar"));
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut usage = WatchedUsage::default();
+        usage.add(1.5, 1024);
+        usage.add(2.0, 2048);
+        assert_eq!(
+            usage,
+            WatchedUsage {
+                instances: 2,
+                cpu: 3.5,
+                memory: 3072,
+            }
+        );
+    }
+}
@@ -26,69 +26,222 @@
 //!     cargo build --release
 //!     ```
 //!
-//! 3. Set environment variables for configuration (such as BROKER_URL):
+//! 3. Write a configuration file (every field is optional):
+//!     ```toml
+//!     [mqtt]
+//!     broker_url = "tcp://localhost:1883"
+//!
+//!     [collection]
+//!     interval = 60 # seconds between two collections
+//!     categories = ["disk", "memory", "cpu", "swap"] # also "load", "uptime", "network", "temperature", "processes", "cgroups", "pressure"
+//!     sample_interval = 5 # also report min/max/mean/p95 of 5 s samples
+//!     ```
+//!
+//!    Every output has its own `enabled` flag and several can be used at once.
+//!    To be scraped by Prometheus, enable the exporter, which serves
+//!    the latest values on `http://<listen>/metrics`:
+//!     ```toml
+//!     [prometheus]
+//!     enabled = true
+//!     listen = "0.0.0.0:9184"
+//!     ```
+//!
+//!    To keep the history in InfluxDB (`udp://host:8089` for a UDP listener):
+//!     ```toml
+//!     [influxdb]
+//!     enabled = true
+//!     url = "http://localhost:8086"
+//!     org = "home"
+//!     bucket = "srvstat"
+//!     token = "..."
+//!     ```
+//!
+//!    Metrics can also be printed (`[console]`) or appended to a JSON lines
+//!    file (`[file]` with a `path`).
+//!
+//!    Alert rules raise WARN/CRIT levels on usage percentages, reported on the
+//!    console and on the retained `srvstat/<host>/alerts/<rule>/<category>`
+//!    topic, which backs a Home Assistant `problem` binary sensor:
+//!     ```toml
+//!     [[alerts]]
+//!     name = "disk_full"
+//!     category = "disk"
+//!     warn = 80
+//!     crit = 90
+//!     duration = 300  # seconds above a level before raising it
+//!     hysteresis = 5  # points below a level before lowering it
+//!     ```
+//!
+//!    Processes that must be running are watched, each backing a Home
+//!    Assistant `running` binary sensor with its instance count and usage:
+//!     ```toml
+//!     [[watch]]
+//!     name = "nginx"
+//!     process = "nginx" # or exe = "/usr/sbin/nginx", or a cmdline regex
+//!     ```
+//!
+//!    Every field can be overridden with a `SRVSTAT_<SECTION>_<FIELD>`
+//!    environment variable, e.g. `SRVSTAT_MQTT_BROKER_URL`, map entries with
+//!    `SRVSTAT_<SECTION>_<FIELD>_<KEY>` (`SRVSTAT_MQTT_DEADBAND_DISK`), and
+//!    the alerts and watches with a TOML inline array (`SRVSTAT_ALERTS`).
+//!
+//! 4. Check the configuration, then run the daemon, or collect a single time with `--once`:
 //!     ```bash
-//!     export BROKER_URL=tcp://localhost:1883
+//!     srvstat config check --config srvstat.toml
+//!     srvstat --config srvstat.toml
+//!     srvstat --config srvstat.toml --once
+//!     ```
 //!
 
+use crate::domain::alerts::alert_engine::AlertEngine;
 use crate::domain::metrics::metric_service::MetricService;
-use crate::domain::metrics::models::Category;
-use crate::domain::ports::MetricProcessor;
-use crate::outbound::metric_writer::MqttMetricWriter;
+use crate::domain::ports::{AlertNotifier, MetricWriter};
+use crate::outbound::fan_out_writer::FanOutWriter;
+use crate::outbound::file_writer::FileMetricWriter;
+use crate::outbound::influx_writer::InfluxMetricWriter;
+use crate::outbound::metric_writer::{ConsoleAlertNotifier, MqttMetricWriter};
+use crate::outbound::prometheus_writer::PrometheusMetricWriter;
+use anyhow::Context;
+use cli::{Cli, Command};
 use config::Config;
 use outbound::{metric_reader::SystemMetricReader, metric_writer::DummyMetricWriter};
-use std::process::exit;
 use std::env;
+use std::process::exit;
+use std::sync::mpsc;
 
+pub mod cli;
 pub mod config;
 pub mod domain;
 pub mod outbound;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let cli = Cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("Usage: srvstat [--config <path>] [--once] | srvstat config check [--config <path>] | srvstat --version");
+        exit(2);
+    });
 
-    if args.len() > 1 && args[1] == "--version" {
+    if cli.command == Command::Version {
         let version = env!("CARGO_PKG_VERSION");
         println!("Application version: {}", version);
         return;
     }
 
-    match Config::from_env() {
-        Ok(config) => {
-            println!("Config broker_url={:?}", config.broker_url);
-            let reader = SystemMetricReader;
-            let writer = MqttMetricWriter::new(config.broker_url);
-            let service = MetricService::new(reader, writer);
-            service.process_metrics(Category::Disk);
-            service.process_metrics(Category::Memory);
-            service.process_metrics(Category::Cpu);
-            service.process_metrics(Category::Swap);
-        }
+    let config = match Config::load(cli.config_path.as_deref()) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Error loading configuration: {}", e);
-            eprintln!("Usage: Set the BROKER_URL environment variable.");
-            println!("Writing values to console :");
-            let reader = SystemMetricReader;
-            let writer = DummyMetricWriter;
-            let service = MetricService::new(reader, writer);
-            service.process_metrics(Category::Disk);
-            service.process_metrics(Category::Memory);
-            service.process_metrics(Category::Cpu);
-            service.process_metrics(Category::Swap);
+            eprintln!("Error loading configuration: {:#}", e);
             exit(1);
         }
+    };
+
+    if cli.command == Command::ConfigCheck {
+        match config.to_toml() {
+            Ok(rendered) => {
+                println!("Configuration is valid.");
+                print!("{}", rendered);
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let reader = SystemMetricReader::new(&config);
+    let (writer, notifiers) = outputs(&config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    if writer.is_empty() {
+        eprintln!("No output configured.");
+        eprintln!("Usage: Set mqtt.broker_url in the config file or the SRVSTAT_MQTT_BROKER_URL environment variable, or enable another output.");
+        println!("Writing values to console :");
+        let service = MetricService::new(reader, DummyMetricWriter);
+        service.process_all(&config.categories());
+        exit(1);
+    }
+    println!(
+        "Writing to {} every {:?}",
+        writer.names().join(", "),
+        config.collection.interval
+    );
+    let mut service =
+        MetricService::new(reader, writer).with_sampling(config.collection.sample_interval);
+    if !config.alerts.is_empty() {
+        service = service.with_alerts(AlertEngine::new(config.alerts.clone()), notifiers);
+    }
+    collect(service, &config, cli.once);
+}
+
+// Every enabled output, a failing one not preventing the others from
+// receiving the metrics, and where alert level changes are reported
+fn outputs(config: &Config) -> anyhow::Result<(FanOutWriter, Vec<Box<dyn AlertNotifier>>)> {
+    let mut writer = FanOutWriter::new();
+    let mut notifiers: Vec<Box<dyn AlertNotifier>> = vec![Box::new(ConsoleAlertNotifier)];
+    if config.mqtt.is_active() {
+        // States are published at least every heartbeat, on the collection
+        // following it, with one more collection to spare
+        let expire_after = config.mqtt.heartbeat + config.collection.interval * 2;
+        let mqtt = MqttMetricWriter::new(&config.mqtt, expire_after)?;
+        notifiers.push(Box::new(mqtt.clone()));
+        writer.add("mqtt", mqtt);
+    }
+    if config.prometheus.enabled {
+        let listen = &config.prometheus.listen;
+        // Series missing from three collections in a row are gone
+        let expire_after = config.collection.interval * 3;
+        let prometheus = PrometheusMetricWriter::new(listen, expire_after)
+            .with_context(|| format!("Unable to listen on {}", listen))?;
+        writer.add("prometheus", prometheus);
     }
+    if config.influxdb.enabled {
+        writer.add("influxdb", InfluxMetricWriter::new(&config.influxdb)?);
+    }
+    if config.console.enabled {
+        writer.add("console", DummyMetricWriter);
+    }
+    if let (true, Some(path)) = (config.file.enabled, &config.file.path) {
+        let file = FileMetricWriter::new(path)
+            .with_context(|| format!("Unable to open {}", path.display()))?;
+        writer.add("file", file);
+    }
+    Ok((writer, notifiers))
+}
+
+// Collect once, or every interval until SIGINT/SIGTERM
+fn collect<W: MetricWriter>(
+    service: MetricService<SystemMetricReader, W>,
+    config: &Config,
+    once: bool,
+) {
+    let categories = &config.categories();
+    if once {
+        service.process_all(categories);
+        service.close();
+        return;
+    }
+
+    let (shutdown_tx, shutdown_rx) = mpsc::channel();
+    if let Err(e) = ctrlc::set_handler(move || {
+        let _ = shutdown_tx.send(());
+    }) {
+        eprintln!("Error setting signal handler: {}", e);
+        exit(1);
+    }
+    service.run(categories, config.collection.interval, &shutdown_rx);
 }
 
 #[cfg(test)]
 mod tests {
-    use std::process::Command;
     use std::env;
-    
+    use std::process::Command;
+
     #[test]
     fn test_version_flag() {
         let output = Command::new("cargo")
-            .args(&["run", "--", "--version"])
+            .args(["run", "--", "--version"])
             .output()
             .expect("failed to execute process");
 
@@ -100,8 +253,17 @@ mod tests {
 
     #[test]
     fn test_no_version_flag() {
+        // Without a broker from the environment, and collecting once, the
+        // daemon cannot keep running
         let output = Command::new("cargo")
-            .args(&["run"])
+            .args(["run", "--", "--once"])
+            .env_clear()
+            .envs(env::vars().filter(|(name, _)| {
+                name == "PATH"
+                    || name == "HOME"
+                    || name.starts_with("CARGO")
+                    || name.starts_with("RUSTUP")
+            }))
             .output()
             .expect("failed to execute process");
 
@@ -109,4 +271,38 @@ mod tests {
 
         assert!(!stdout.contains("Application version:"));
     }
+
+    #[test]
+    fn test_config_check() {
+        let path = env::temp_dir().join(format!("srvstat-check-{}.toml", std::process::id()));
+        std::fs::write(&path, "[collection]\ninterval = 42\n").unwrap();
+        let output = Command::new("cargo")
+            .args(["run", "--", "config", "check", "--config"])
+            .arg(&path)
+            .output()
+            .expect("failed to execute process");
+        std::fs::remove_file(&path).unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success());
+        assert!(stdout.contains("Configuration is valid."));
+        assert!(stdout.contains("interval = 42"));
+    }
+
+    #[test]
+    fn test_config_check_invalid() {
+        let output = Command::new("cargo")
+            .args([
+                "run",
+                "--",
+                "config",
+                "check",
+                "--config",
+                "/nonexistent/srvstat.toml",
+            ])
+            .output()
+            .expect("failed to execute process");
+
+        assert!(!output.status.success());
+    }
 }
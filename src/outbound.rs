@@ -1,2 +1,9 @@
+pub mod cgroup_reader;
+pub mod fan_out_writer;
+pub mod file_writer;
+pub mod influx_writer;
+pub mod message_queue;
 pub mod metric_reader;
 pub mod metric_writer;
+pub mod pressure_reader;
+pub mod prometheus_writer;
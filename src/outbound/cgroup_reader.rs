@@ -0,0 +1,303 @@
+use crate::domain::filter::Filter;
+use crate::domain::metrics::models::Quantity;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Counters and limits read from the interface files of a cgroup.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct CgroupStats {
+    memory_current: Option<u64>,
+    // None when unlimited
+    memory_max: Option<u64>,
+    usage_usec: Option<u64>,
+    nr_throttled: Option<u64>,
+    // Bytes read and written, summed over every device
+    io: Option<(u64, u64)>,
+}
+
+/// Usage of a cgroup, its rates covering the time since the previous read
+/// (none on the first one).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CgroupUsage {
+    /// Path below the cgroup root, e.g. `system.slice/nginx.service`.
+    pub path: String,
+    stats: CgroupStats,
+    // Percent of one core
+    cpu_usage: Option<f64>,
+    // Bytes read and written per second
+    io_rates: Option<(f64, f64)>,
+}
+
+impl CgroupUsage {
+    fn new(path: String, stats: CgroupStats, previous: Option<(&CgroupStats, Duration)>) -> Self {
+        let rate = |current: fn(&CgroupStats) -> Option<u64>| {
+            let (previous, elapsed) = previous?;
+            let seconds = elapsed.as_secs_f64();
+            if seconds <= 0.0 {
+                return None;
+            }
+            let delta = current(&stats)?.saturating_sub(current(previous)?);
+            Some(delta as f64 / seconds)
+        };
+        // Microseconds of CPU time per second, in percent of one core
+        let cpu_usage = rate(|stats| stats.usage_usec).map(|usec| (usec / 1e3).round() / 10.0);
+        let read = rate(|stats| stats.io.map(|(read, _)| read));
+        let written = rate(|stats| stats.io.map(|(_, written)| written));
+        let io_rates = read.zip(written).map(|(read, written)| {
+            (
+                (read * 100.0).round() / 100.0,
+                (written * 100.0).round() / 100.0,
+            )
+        });
+        CgroupUsage {
+            path,
+            stats,
+            cpu_usage,
+            io_rates,
+        }
+    }
+
+    /// The memory used, and against its limit when there is one, then the
+    /// CPU usage, throttling and I/O throughput, when known.
+    pub fn values(&self) -> Vec<(Quantity, f64)> {
+        let mut values = Vec::new();
+        if let Some(used) = self.stats.memory_current {
+            values.push((Quantity::MemoryUsed, used as f64));
+        }
+        if let Some(max) = self.stats.memory_max {
+            values.push((Quantity::MemoryMax, max as f64));
+            if let (Some(used), true) = (self.stats.memory_current, max > 0) {
+                let percent = used as f64 / max as f64 * 100.0;
+                values.push((Quantity::MemoryUsage, (percent * 10.0).round() / 10.0));
+            }
+        }
+        if let Some(usage) = self.cpu_usage {
+            values.push((Quantity::CpuUsage, usage));
+        }
+        if let Some(throttled) = self.stats.nr_throttled {
+            values.push((Quantity::Throttled, throttled as f64));
+        }
+        if let Some((read, written)) = self.io_rates {
+            values.push((Quantity::IoRead, read));
+            values.push((Quantity::IoWrite, written));
+        }
+        values
+    }
+}
+
+/// Reads the cgroups of a cgroup v2 hierarchy whose path passes a filter.
+pub struct CgroupReader {
+    root: PathBuf,
+    filter: Filter,
+    // Stats of the previous read, the baseline of the rates
+    previous: Mutex<HashMap<String, (CgroupStats, Instant)>>,
+}
+
+impl CgroupReader {
+    pub fn new(root: &Path, filter: Filter) -> Self {
+        CgroupReader {
+            root: root.to_path_buf(),
+            filter,
+            previous: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Every selected cgroup below the root, sorted by path.
+    pub fn read(&self) -> Vec<CgroupUsage> {
+        let now = Instant::now();
+        let mut previous = self.previous.lock().unwrap_or_else(|e| e.into_inner());
+        let mut current = HashMap::new();
+        let usages = cgroup_paths(&self.root)
+            .into_iter()
+            .filter(|path| self.filter.allows(path))
+            .map(|path| {
+                let stats = read_stats(&self.root.join(&path));
+                let baseline = previous
+                    .get(&path)
+                    .map(|(stats, at)| (stats, now.duration_since(*at)));
+                let usage = CgroupUsage::new(path.clone(), stats.clone(), baseline);
+                current.insert(path, (stats, now));
+                usage
+            })
+            .collect();
+        // Forget the cgroups that were removed
+        *previous = current;
+        usages
+    }
+}
+
+// Paths of every cgroup below `root`, which is itself left out
+fn cgroup_paths(root: &Path) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let Ok(entries) = fs::read_dir(root.join(&relative)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                let path = relative.join(entry.file_name());
+                paths.push(path.to_string_lossy().into_owned());
+                pending.push(path);
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+// Files of controllers that are not enabled for the cgroup are missing
+fn read_stats(dir: &Path) -> CgroupStats {
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+    let cpu_stat = read("cpu.stat").unwrap_or_default();
+    CgroupStats {
+        memory_current: read("memory.current").and_then(|value| value.trim().parse().ok()),
+        memory_max: read("memory.max").and_then(|value| value.trim().parse().ok()),
+        usage_usec: stat_field(&cpu_stat, "usage_usec"),
+        nr_throttled: stat_field(&cpu_stat, "nr_throttled"),
+        io: read("io.stat").map(|content| io_bytes(&content)),
+    }
+}
+
+// Value of a `key value` line of a flat keyed file such as cpu.stat
+fn stat_field(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+// Bytes read and written over every device of an io.stat file, whose lines
+// look like `8:0 rbytes=1024 wbytes=0 rios=1 wios=0 dbytes=0 dios=0`
+fn io_bytes(content: &str) -> (u64, u64) {
+    let mut bytes = (0, 0);
+    for field in content.split_whitespace() {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        let value: u64 = value.parse().unwrap_or(0);
+        match key {
+            "rbytes" => bytes.0 += value,
+            "wbytes" => bytes.1 += value,
+            _ => {}
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // A cgroup root with a limited service in an unlimited slice, and a slice
+    // without the memory and io controllers
+    fn cgroup_fixture(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("srvstat-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let service = root.join("system.slice/nginx.service");
+        fs::create_dir_all(&service).unwrap();
+        fs::write(service.join("memory.current"), "536870912\n").unwrap();
+        fs::write(service.join("memory.max"), "1073741824\n").unwrap();
+        fs::write(
+            service.join("cpu.stat"),
+            "usage_usec 2000000\nuser_usec 1500000\nsystem_usec 500000\nnr_periods 10\nnr_throttled 4\nthrottled_usec 800\n",
+        )
+        .unwrap();
+        fs::write(
+            service.join("io.stat"),
+            "8:0 rbytes=4096 wbytes=1024 rios=2 wios=1 dbytes=0 dios=0\n259:0 rbytes=1024 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n",
+        )
+        .unwrap();
+        fs::write(root.join("system.slice/memory.max"), "max\n").unwrap();
+        fs::write(root.join("system.slice/memory.current"), "600000000\n").unwrap();
+        let user = root.join("user.slice");
+        fs::create_dir_all(&user).unwrap();
+        fs::write(user.join("cpu.stat"), "usage_usec 100\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_cgroup_paths() {
+        let root = cgroup_fixture("cgroup-paths");
+        assert_eq!(
+            cgroup_paths(&root),
+            vec!["system.slice", "system.slice/nginx.service", "user.slice"]
+        );
+        assert!(cgroup_paths(&root.join("nonexistent")).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_read() {
+        let root = cgroup_fixture("cgroup-read");
+        let filter = Filter::new(vec!["system.slice*".to_string()], vec![]);
+        let reader = CgroupReader::new(&root, filter);
+        let usages = reader.read();
+        fs::remove_dir_all(&root).unwrap();
+
+        let paths: Vec<&str> = usages.iter().map(|usage| usage.path.as_str()).collect();
+        assert_eq!(paths, vec!["system.slice", "system.slice/nginx.service"]);
+        // Unlimited, and no rate on the first read
+        assert_eq!(
+            usages[0].values(),
+            vec![(Quantity::MemoryUsed, 600000000.0)]
+        );
+        assert_eq!(
+            usages[1].values(),
+            vec![
+                (Quantity::MemoryUsed, 536870912.0),
+                (Quantity::MemoryMax, 1073741824.0),
+                (Quantity::MemoryUsage, 50.0),
+                (Quantity::Throttled, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        let previous = CgroupStats {
+            usage_usec: Some(1_000_000),
+            io: Some((1000, 0)),
+            ..CgroupStats::default()
+        };
+        let stats = CgroupStats {
+            usage_usec: Some(4_000_000),
+            io: Some((9000, 100)),
+            ..CgroupStats::default()
+        };
+        let usage = CgroupUsage::new(
+            "a".to_string(),
+            stats.clone(),
+            Some((&previous, Duration::from_secs(2))),
+        );
+        assert_eq!(
+            usage.values(),
+            vec![
+                (Quantity::CpuUsage, 150.0),
+                (Quantity::IoRead, 4000.0),
+                (Quantity::IoWrite, 50.0),
+            ]
+        );
+
+        let usage = CgroupUsage::new("a".to_string(), stats, Some((&previous, Duration::ZERO)));
+        assert!(usage.values().is_empty());
+    }
+
+    #[test]
+    fn test_stat_field() {
+        let content = "usage_usec 2000000\nnr_throttled 4\n";
+        assert_eq!(stat_field(content, "nr_throttled"), Some(4));
+        assert_eq!(stat_field(content, "usage"), None);
+    }
+
+    #[test]
+    fn test_io_bytes() {
+        let content = "8:0 rbytes=4096 wbytes=1024 rios=2\n8:16 rbytes=1 wbytes=2\n";
+        assert_eq!(io_bytes(content), (4097, 1026));
+        assert_eq!(io_bytes(""), (0, 0));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::domain::metrics::models::Metric;
+use crate::domain::ports::{MetricError, MetricWriter, Snapshot};
+
+/// Forwards every metric to several writers.
+///
+/// Each writer gets every metric whatever happened to the others; the failures
+/// are reported together, prefixed with the name of the failing backend.
+#[derive(Default)]
+pub struct FanOutWriter {
+    writers: Vec<(String, Box<dyn MetricWriter>)>,
+}
+
+impl FanOutWriter {
+    pub fn new() -> Self {
+        FanOutWriter::default()
+    }
+
+    pub fn add(&mut self, name: &str, writer: impl MetricWriter + 'static) {
+        self.writers.push((name.to_string(), Box::new(writer)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writers.is_empty()
+    }
+
+    /// Names of the backends, in the order they are written to.
+    pub fn names(&self) -> Vec<&str> {
+        self.writers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+impl FanOutWriter {
+    // Apply `action` to every writer, then report the failures together
+    fn each<F>(&self, subject: &str, action: F) -> Result<(), MetricError>
+    where
+        F: Fn(&dyn MetricWriter) -> Result<(), MetricError>,
+    {
+        let failures: Vec<String> = self
+            .writers
+            .iter()
+            .filter_map(|(name, writer)| {
+                action(writer.as_ref())
+                    .err()
+                    .map(|e| format!("{}: {}", name, e))
+            })
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(MetricError::Write {
+                metric: subject.to_string(),
+                reason: failures.join("; "),
+            })
+        }
+    }
+}
+
+impl MetricWriter for FanOutWriter {
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
+        self.each(&metric.to_string(), |writer| writer.write(metric.clone()))
+    }
+
+    fn write_batch(&self, snapshot: &Snapshot) -> Result<(), MetricError> {
+        let subject = format!("{} metric(s)", snapshot.metrics.len());
+        self.each(&subject, |writer| writer.write_batch(snapshot))
+    }
+
+    fn flush(&self) -> Result<(), MetricError> {
+        self.each("buffered metrics", |writer| writer.flush())
+    }
+
+    fn close(&self) {
+        for (_, writer) in &self.writers {
+            writer.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::models::{Category, Percentage};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder {
+        metrics: RefCell<Vec<Metric>>,
+        closed: Cell<bool>,
+    }
+
+    struct RecordingWriter(Rc<Recorder>);
+
+    impl MetricWriter for RecordingWriter {
+        fn write(&self, metric: Metric) -> Result<(), MetricError> {
+            self.0.metrics.borrow_mut().push(metric);
+            Ok(())
+        }
+
+        fn close(&self) {
+            self.0.closed.set(true);
+        }
+    }
+
+    struct FailingWriter;
+
+    impl MetricWriter for FailingWriter {
+        fn write(&self, metric: Metric) -> Result<(), MetricError> {
+            Err(MetricError::Write {
+                metric: metric.to_string(),
+                reason: "backend down".to_string(),
+            })
+        }
+    }
+
+    fn cpu() -> Metric {
+        Metric::Percent("tux".to_string(), Category::Cpu, Percentage(12))
+    }
+
+    #[test]
+    fn test_writes_to_every_backend() {
+        let (first, second) = (Rc::new(Recorder::default()), Rc::new(Recorder::default()));
+        let mut writer = FanOutWriter::new();
+        writer.add("first", RecordingWriter(Rc::clone(&first)));
+        writer.add("second", RecordingWriter(Rc::clone(&second)));
+        assert_eq!(writer.names(), vec!["first", "second"]);
+
+        writer.write(cpu()).unwrap();
+        writer.close();
+
+        for recorder in [first, second] {
+            assert_eq!(*recorder.metrics.borrow(), vec![cpu()]);
+            assert!(recorder.closed.get());
+        }
+    }
+
+    #[test]
+    fn test_failure_is_isolated() {
+        let recorder = Rc::new(Recorder::default());
+        let mut writer = FanOutWriter::new();
+        writer.add("influxdb", FailingWriter);
+        writer.add("console", RecordingWriter(Rc::clone(&recorder)));
+
+        let error = writer.write(cpu()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unable to write tux-CPU: 12%: influxdb: Unable to write tux-CPU: 12%: backend down"
+        );
+        assert_eq!(recorder.metrics.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_batch_failure_is_isolated() {
+        let recorder = Rc::new(Recorder::default());
+        let mut writer = FanOutWriter::new();
+        writer.add("influxdb", FailingWriter);
+        writer.add("console", RecordingWriter(Rc::clone(&recorder)));
+
+        let mut snapshot = Snapshot::new();
+        snapshot.push(Ok(cpu()));
+        snapshot.push(Ok(cpu()));
+        let error = writer.write_batch(&snapshot).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unable to write 2 metric(s): influxdb: 2 of 2"));
+        assert_eq!(recorder.metrics.borrow().len(), 2);
+        assert!(writer.flush().is_ok());
+    }
+
+    #[test]
+    fn test_empty() {
+        let writer = FanOutWriter::new();
+        assert!(writer.is_empty());
+        assert!(writer.write(cpu()).is_ok());
+    }
+}
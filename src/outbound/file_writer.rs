@@ -0,0 +1,224 @@
+use crate::domain::metrics::models::{Category, Metric, Percentage};
+use crate::domain::ports::{MetricError, MetricWriter, Snapshot};
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Appends every metric to a local file, one JSON object per line.
+pub struct FileMetricWriter {
+    file: Mutex<File>,
+}
+
+impl FileMetricWriter {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileMetricWriter {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl MetricWriter for FileMetricWriter {
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
+        let line = record(&metric, timestamp(SystemTime::now())).to_string();
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(file, "{}", line).map_err(|e| MetricError::Write {
+            metric: metric.to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    // Every record of a collection shares its timestamp, and the collection is
+    // appended with a single write
+    fn write_batch(&self, snapshot: &Snapshot) -> Result<(), MetricError> {
+        let timestamp = timestamp(snapshot.timestamp);
+        let lines: String = snapshot
+            .metrics
+            .iter()
+            .map(|metric| format!("{}\n", record(metric, timestamp)))
+            .collect();
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(lines.as_bytes())
+            .map_err(|e| MetricError::Write {
+                metric: format!("{} metric(s)", snapshot.metrics.len()),
+                reason: e.to_string(),
+            })
+    }
+
+    fn close(&self) {
+        let file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.sync_all() {
+            eprintln!("Error syncing metrics file: {}", e);
+        }
+    }
+}
+
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn record(metric: &Metric, timestamp: u64) -> Value {
+    let (host, category) = match metric {
+        Metric::Percent(host, category, _)
+        | Metric::Used(host, category, _, _)
+        | Metric::Window(host, category, _)
+        | Metric::Value(host, category, _, _)
+        | Metric::Top(host, category, _) => (host, category),
+    };
+    let mut record = json!({
+        "timestamp": timestamp,
+        "host": host,
+        "category": category.kind(),
+    });
+    match category {
+        Category::Filesystem(mount) => record["mount"] = json!(mount),
+        Category::Core(index) => record["core"] = json!(index),
+        Category::Interface(name) => record["interface"] = json!(name),
+        Category::Sensor(label) => record["sensor"] = json!(label),
+        Category::Watched(name) => record["watch"] = json!(name),
+        Category::Cgroup(path) => record["cgroup"] = json!(path),
+        Category::Psi(resource) => record["resource"] = json!(resource),
+        _ => {}
+    }
+    match metric {
+        Metric::Percent(_, _, Percentage(value)) => record["percent"] = json!(value),
+        Metric::Used(_, _, used, total) => {
+            record["used"] = json!(used);
+            record["total"] = json!(total);
+        }
+        Metric::Window(_, _, window) => {
+            record["window"] = json!({
+                "min": window.min.0,
+                "max": window.max.0,
+                "mean": window.mean.0,
+                "p95": window.p95.0,
+                "samples": window.samples,
+            });
+        }
+        Metric::Value(_, _, quantity, value) => record[quantity.name()] = json!(value),
+        Metric::Top(_, _, processes) => {
+            record["processes"] = json!(processes
+                .iter()
+                .map(|process| json!({
+                    "name": process.name,
+                    "pid": process.pid,
+                    "cpu": process.cpu,
+                    "memory": process.memory,
+                }))
+                .collect::<Vec<_>>());
+        }
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::models::{ProcessUsage, Window};
+    use std::{env, fs};
+
+    #[test]
+    fn test_record() {
+        let metric = Metric::Used(
+            "tux".to_string(),
+            Category::Filesystem("/home".to_string()),
+            25,
+            100,
+        );
+        assert_eq!(
+            record(&metric, 1700000000).to_string(),
+            r#"{"category":"filesystem","host":"tux","mount":"/home","timestamp":1700000000,"total":100,"used":25}"#
+        );
+
+        let window = Metric::Window(
+            "tux".to_string(),
+            Category::Core(0),
+            Window {
+                min: Percentage(5),
+                max: Percentage(95),
+                mean: Percentage(30),
+                p95: Percentage(90),
+                samples: 12,
+            },
+        );
+        assert_eq!(
+            record(&window, 1).to_string(),
+            r#"{"category":"core","core":0,"host":"tux","timestamp":1,"window":{"max":95,"mean":30,"min":5,"p95":90,"samples":12}}"#
+        );
+
+        let top = Metric::Top(
+            "tux".to_string(),
+            Category::Cpu,
+            vec![ProcessUsage {
+                name: "cargo".to_string(),
+                pid: 99,
+                cpu: 180.5,
+                memory: 2048,
+            }],
+        );
+        assert_eq!(
+            record(&top, 1).to_string(),
+            r#"{"category":"cpu","host":"tux","processes":[{"cpu":180.5,"memory":2048,"name":"cargo","pid":99}],"timestamp":1}"#
+        );
+    }
+
+    #[test]
+    fn test_appends_lines() {
+        let path = env::temp_dir().join(format!("srvstat-metrics-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        for value in [1, 2] {
+            let writer = FileMetricWriter::new(&path).unwrap();
+            writer
+                .write(Metric::Percent(
+                    "tux".to_string(),
+                    Category::Cpu,
+                    Percentage(value),
+                ))
+                .unwrap();
+            writer.close();
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let values: Vec<u64> = content
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<Value>(line).unwrap()["percent"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_batch_shares_timestamp() {
+        let path = env::temp_dir().join(format!("srvstat-batch-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let writer = FileMetricWriter::new(&path).unwrap();
+        let mut snapshot = Snapshot::new();
+        snapshot.timestamp = UNIX_EPOCH + std::time::Duration::from_secs(1700000000);
+        snapshot.push(Ok(Metric::Percent(
+            "tux".to_string(),
+            Category::Memory,
+            Percentage(50),
+        )));
+        snapshot.push(Ok(Metric::Used("tux".to_string(), Category::Memory, 4, 8)));
+        writer.write_batch(&snapshot).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let records: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|record| record["timestamp"] == 1700000000));
+    }
+}
@@ -0,0 +1,529 @@
+use crate::config::InfluxConfig;
+use crate::domain::metrics::models::{Category, Metric, Percentage, ProcessUsage};
+use crate::domain::ports::{MetricError, MetricWriter, Snapshot};
+use std::collections::VecDeque;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Measurement every line is written to.
+const MEASUREMENT: &str = "srvstat";
+/// Lines sent in a single UDP datagram stay below a common MTU.
+const MAX_DATAGRAM: usize = 1400;
+
+/// Errors raised while setting up the InfluxDB writer.
+#[derive(Debug, Error)]
+pub enum InfluxError {
+    #[error("Unsupported InfluxDB URL {0}, expected http(s):// or udp://")]
+    InvalidUrl(String),
+    #[error("Unable to open UDP socket to {addr}: {source}")]
+    Udp { addr: String, source: io::Error },
+}
+
+enum Transport {
+    /// InfluxDB v2 `/api/v2/write` endpoint.
+    Http {
+        agent: ureq::Agent,
+        write_url: String,
+        token: Option<String>,
+    },
+    /// UDP listener, e.g. InfluxDB 1.x or Telegraf `socket_listener`, which
+    /// have no way to be told the precision and expect nanoseconds.
+    Udp(UdpSocket),
+}
+
+/// Renders metrics as InfluxDB line protocol and sends them in batches.
+///
+/// Lines are buffered until `batch_size` of them are pending; the remaining
+/// ones are sent when the writer is flushed, at the end of every collection.
+/// When sending fails, up to `buffer_size` lines are kept for the next attempt.
+pub struct InfluxMetricWriter {
+    transport: Transport,
+    batch_size: usize,
+    buffer_size: usize,
+    pending: Mutex<VecDeque<String>>,
+}
+
+impl InfluxMetricWriter {
+    pub fn new(config: &InfluxConfig) -> Result<Self, InfluxError> {
+        let transport = match config.url.split_once("://") {
+            Some(("http" | "https", _)) => {
+                let mut write_url = format!(
+                    "{}/api/v2/write?bucket={}&precision=ns",
+                    config.url.trim_end_matches('/'),
+                    encode(&config.bucket)
+                );
+                if let Some(org) = &config.org {
+                    write_url.push_str(&format!("&org={}", encode(org)));
+                }
+                Transport::Http {
+                    agent: ureq::AgentBuilder::new().timeout(config.timeout).build(),
+                    write_url,
+                    token: config.token.clone(),
+                }
+            }
+            Some(("udp", addr)) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .and_then(|socket| socket.connect(addr).map(|()| socket))
+                    .map_err(|source| InfluxError::Udp {
+                        addr: addr.to_string(),
+                        source,
+                    })?;
+                Transport::Udp(socket)
+            }
+            _ => return Err(InfluxError::InvalidUrl(config.url.clone())),
+        };
+        Ok(InfluxMetricWriter {
+            transport,
+            batch_size: config.batch_size.max(1),
+            buffer_size: config.buffer_size.max(config.batch_size),
+            pending: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    // Send every pending line, keeping the most recent ones on failure
+    fn send_pending(&self, pending: &mut VecDeque<String>) -> Result<(), String> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<&str> = pending.iter().map(String::as_str).collect();
+        match self.send(&lines) {
+            Ok(()) => {
+                pending.clear();
+                Ok(())
+            }
+            Err(e) => {
+                while pending.len() > self.buffer_size {
+                    pending.pop_front();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn send(&self, lines: &[&str]) -> Result<(), String> {
+        match &self.transport {
+            Transport::Http {
+                agent,
+                write_url,
+                token,
+            } => {
+                let mut request = agent
+                    .post(write_url)
+                    .set("Content-Type", "text/plain; charset=utf-8");
+                if let Some(token) = token {
+                    request = request.set("Authorization", &format!("Token {}", token));
+                }
+                request
+                    .send_string(&lines.join("\n"))
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+            Transport::Udp(socket) => {
+                for datagram in datagrams(lines) {
+                    socket
+                        .send(datagram.as_bytes())
+                        .map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl MetricWriter for InfluxMetricWriter {
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.extend(lines(&metric, timestamp(SystemTime::now())));
+        if pending.len() < self.batch_size {
+            return Ok(());
+        }
+        self.send_pending(&mut pending)
+            .map_err(|reason| MetricError::Write {
+                metric: metric.to_string(),
+                reason,
+            })
+    }
+
+    // Lines of a collection share its timestamp, and are sent together
+    fn write_batch(&self, snapshot: &Snapshot) -> Result<(), MetricError> {
+        let timestamp = timestamp(snapshot.timestamp);
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.extend(
+            snapshot
+                .metrics
+                .iter()
+                .flat_map(|metric| lines(metric, timestamp)),
+        );
+        if pending.len() < self.batch_size {
+            return Ok(());
+        }
+        self.send_pending(&mut pending)
+            .map_err(|reason| MetricError::Write {
+                metric: format!("{} metric(s)", snapshot.metrics.len()),
+                reason,
+            })
+    }
+
+    fn flush(&self) -> Result<(), MetricError> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let count = pending.len();
+        self.send_pending(&mut pending)
+            .map_err(|reason| MetricError::Write {
+                metric: format!("{} line(s)", count),
+                reason,
+            })
+    }
+
+    fn close(&self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Error: {}", e);
+        }
+    }
+}
+
+// Nanoseconds since the epoch, the default precision of every transport
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// Renders a metric as lines: one per process for the top processes, ranked
+/// by a `rank` tag, and a single one for any other metric. Percent, used/total
+/// and window readings of the same category share a series, so InfluxDB
+/// merges their fields.
+fn lines(metric: &Metric, timestamp: u64) -> Vec<String> {
+    let (host, category, fields) = match metric {
+        Metric::Percent(host, category, Percentage(value)) => {
+            (host, category, format!("percent={}i", value))
+        }
+        Metric::Used(host, category, used, total) => {
+            (host, category, format!("used={}i,total={}i", used, total))
+        }
+        Metric::Window(host, category, window) => (
+            host,
+            category,
+            format!(
+                "min={}i,max={}i,mean={}i,p95={}i,samples={}i",
+                window.min.0, window.max.0, window.mean.0, window.p95.0, window.samples
+            ),
+        ),
+        Metric::Value(host, category, quantity, value) => {
+            (host, category, format!("{}={}", quantity.name(), value))
+        }
+        Metric::Top(host, by, processes) => return top_lines(host, by, processes, timestamp),
+    };
+    vec![format!(
+        "{},{} {} {}",
+        MEASUREMENT,
+        tags(host, category),
+        fields,
+        timestamp
+    )]
+}
+
+// A line per process, tagged with what they are ranked by
+fn top_lines(host: &str, by: &Category, processes: &[ProcessUsage], timestamp: u64) -> Vec<String> {
+    let tags = tags(host, &Category::Processes);
+    processes
+        .iter()
+        .enumerate()
+        .map(|(rank, process)| {
+            format!(
+                "{},{},by={},rank={} process=\"{}\",pid={}i,cpu={},memory={}i {}",
+                MEASUREMENT,
+                tags,
+                by.kind(),
+                rank + 1,
+                process.name.replace('\\', "\\\\").replace('"', "\\\""),
+                process.pid,
+                process.cpu,
+                process.memory,
+                timestamp
+            )
+        })
+        .collect()
+}
+
+// The host, category and mount point, core, interface, sensor, watch, cgroup
+// or resource tags
+fn tags(host: &str, category: &Category) -> String {
+    let mut tags = format!("host={},category={}", escape(host), category.kind());
+    match category {
+        Category::Filesystem(mount) => tags.push_str(&format!(",mount={}", escape(mount))),
+        Category::Core(index) => tags.push_str(&format!(",core={}", index)),
+        Category::Interface(name) => tags.push_str(&format!(",interface={}", escape(name))),
+        Category::Sensor(label) => tags.push_str(&format!(",sensor={}", escape(label))),
+        Category::Watched(name) => tags.push_str(&format!(",watch={}", escape(name))),
+        Category::Cgroup(path) => tags.push_str(&format!(",cgroup={}", escape(path))),
+        Category::Psi(resource) => tags.push_str(&format!(",resource={}", escape(resource))),
+        _ => {}
+    }
+    tags
+}
+
+// Escape the characters separating tags and fields
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+// Group lines into datagrams of at most MAX_DATAGRAM bytes (a longer line
+// being sent on its own)
+fn datagrams(lines: &[&str]) -> Vec<String> {
+    let mut datagrams: Vec<String> = Vec::new();
+    for line in lines {
+        match datagrams.last_mut() {
+            Some(datagram) if datagram.len() + 1 + line.len() <= MAX_DATAGRAM => {
+                datagram.push('\n');
+                datagram.push_str(line);
+            }
+            _ => datagrams.push(line.to_string()),
+        }
+    }
+    datagrams
+}
+
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::models::{Quantity, Window};
+    use std::time::Duration;
+
+    fn config(url: &str, batch_size: usize) -> InfluxConfig {
+        InfluxConfig {
+            enabled: true,
+            url: url.to_string(),
+            org: Some("home lab".to_string()),
+            bucket: "metrics".to_string(),
+            token: Some("secret".to_string()),
+            batch_size,
+            ..InfluxConfig::default()
+        }
+    }
+
+    fn cpu(value: u8) -> Metric {
+        Metric::Percent("tux".to_string(), Category::Cpu, Percentage(value))
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(
+            lines(&cpu(12), 1700000000),
+            vec!["srvstat,host=tux,category=cpu percent=12i 1700000000"]
+        );
+        let used = Metric::Used(
+            "my host".to_string(),
+            Category::Filesystem("/mnt/a,b".to_string()),
+            25,
+            100,
+        );
+        assert_eq!(
+            lines(&used, 1700000000),
+            vec!["srvstat,host=my\\ host,category=filesystem,mount=/mnt/a\\,b used=25i,total=100i 1700000000"]
+        );
+        let core = Metric::Percent("tux".to_string(), Category::Core(3), Percentage(7));
+        assert_eq!(
+            lines(&core, 1),
+            vec!["srvstat,host=tux,category=core,core=3 percent=7i 1"]
+        );
+        let window = Metric::Window(
+            "tux".to_string(),
+            Category::Cpu,
+            Window {
+                min: Percentage(5),
+                max: Percentage(95),
+                mean: Percentage(30),
+                p95: Percentage(90),
+                samples: 12,
+            },
+        );
+        assert_eq!(
+            lines(&window, 1),
+            vec!["srvstat,host=tux,category=cpu min=5i,max=95i,mean=30i,p95=90i,samples=12i 1"]
+        );
+        let temperature = Metric::Value(
+            "tux".to_string(),
+            Category::Sensor("nvme Composite".to_string()),
+            Quantity::Temperature,
+            38.85,
+        );
+        assert_eq!(
+            lines(&temperature, 1),
+            vec!["srvstat,host=tux,category=sensor,sensor=nvme\\ Composite temperature=38.85 1"]
+        );
+    }
+
+    #[test]
+    fn test_lines_top() {
+        let process = |name: &str, pid| ProcessUsage {
+            name: name.to_string(),
+            pid,
+            cpu: 12.5,
+            memory: 4096,
+        };
+        let top = Metric::Top(
+            "tux".to_string(),
+            Category::Memory,
+            vec![process("postgres", 42), process("my \"app\"", 7)],
+        );
+        assert_eq!(
+            lines(&top, 1),
+            vec![
+                "srvstat,host=tux,category=processes,by=memory,rank=1 process=\"postgres\",pid=42i,cpu=12.5,memory=4096i 1",
+                "srvstat,host=tux,category=processes,by=memory,rank=2 process=\"my \\\"app\\\"\",pid=7i,cpu=12.5,memory=4096i 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::new(1700000000, 5);
+        assert_eq!(timestamp(time), 1700000000000000005);
+        assert_eq!(timestamp(UNIX_EPOCH - Duration::from_secs(1)), 0);
+    }
+
+    #[test]
+    fn test_datagrams() {
+        let long = "x".repeat(MAX_DATAGRAM);
+        let lines = ["a", "b", long.as_str(), "c"];
+        assert_eq!(
+            datagrams(&lines),
+            vec!["a\nb".to_string(), long.clone(), "c".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        assert!(matches!(
+            InfluxMetricWriter::new(&config("localhost:8086", 1)),
+            Err(InfluxError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_http_batches() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("bucket".into(), "metrics".into()),
+                mockito::Matcher::UrlEncoded("org".into(), "home lab".into()),
+                mockito::Matcher::UrlEncoded("precision".into(), "ns".into()),
+            ]))
+            .match_header("authorization", "Token secret")
+            .match_body(mockito::Matcher::Regex(
+                r"^srvstat,host=tux,category=cpu percent=1i \d+\nsrvstat,host=tux,category=cpu percent=2i \d+$"
+                    .to_string(),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let writer = InfluxMetricWriter::new(&config(&server.url(), 2)).unwrap();
+        writer.write(cpu(1)).unwrap();
+        writer.write(cpu(2)).unwrap();
+        writer.close();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_http_failure_keeps_lines() {
+        let mut server = mockito::Server::new();
+        let failing = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::Any)
+            .with_status(503)
+            .expect(1)
+            .create();
+
+        let writer = InfluxMetricWriter::new(&config(&server.url(), 1)).unwrap();
+        assert!(matches!(
+            writer.write(cpu(1)),
+            Err(MetricError::Write { .. })
+        ));
+        failing.assert();
+        failing.remove();
+
+        let mock = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::Any)
+            .match_body(mockito::Matcher::Regex(
+                r"percent=1i .*\n.*percent=2i".to_string(),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create();
+        writer.write(cpu(2)).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_http_batch_flush() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/api/v2/write")
+            .match_query(mockito::Matcher::Any)
+            .match_body(
+                "srvstat,host=tux,category=cpu percent=1i 1700000000000000000\n\
+                 srvstat,host=tux,category=cpu percent=2i 1700000000000000000",
+            )
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let writer = InfluxMetricWriter::new(&config(&server.url(), 100)).unwrap();
+        let mut snapshot = Snapshot::new();
+        snapshot.timestamp = UNIX_EPOCH + Duration::from_secs(1700000000);
+        snapshot.push(Ok(cpu(1)));
+        snapshot.push(Ok(cpu(2)));
+        writer.write_batch(&snapshot).unwrap();
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_udp() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let url = format!("udp://{}", listener.local_addr().unwrap());
+
+        let writer = InfluxMetricWriter::new(&config(&url, 10)).unwrap();
+        let before = SystemTime::now();
+        writer.write(cpu(1)).unwrap();
+        writer.write(cpu(2)).unwrap();
+        let after = SystemTime::now();
+        writer.close();
+
+        let mut buf = [0; MAX_DATAGRAM];
+        let len = listener.recv(&mut buf).unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..len]);
+        let lines: Vec<&str> = datagram.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("srvstat,host=tux,category=cpu percent=1i "));
+        // Listeners without a precision read nanoseconds
+        let (_, sent) = lines[0].rsplit_once(' ').unwrap();
+        let sent: u64 = sent.parse().unwrap();
+        assert!((timestamp(before)..=timestamp(after)).contains(&sent));
+    }
+}
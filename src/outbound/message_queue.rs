@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A message waiting to be published.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub topic: String,
+    pub payload: String,
+    /// Kept by the broker for new subscribers, like the alert levels.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retained: bool,
+}
+
+/// Bounded FIFO of messages that could not be published.
+///
+/// When full, the oldest message is dropped. If a file is given, each message
+/// is appended to it (one JSON message per line) and the file is reloaded on
+/// creation, so queued readings survive a restart. The file is rewritten after
+/// a flush, or once the dropped messages make it twice the capacity.
+#[derive(Debug)]
+pub struct MessageQueue {
+    messages: VecDeque<QueuedMessage>,
+    capacity: usize,
+    path: Option<PathBuf>,
+    // Lines in the file, dropped messages included
+    file_lines: usize,
+}
+
+impl MessageQueue {
+    pub fn new(capacity: usize) -> Self {
+        MessageQueue {
+            messages: VecDeque::new(),
+            capacity,
+            path: None,
+            file_lines: 0,
+        }
+    }
+
+    /// Creates a queue persisted to `path`, loading the messages already saved there.
+    pub fn with_file(capacity: usize, path: PathBuf) -> io::Result<Self> {
+        let mut queue = MessageQueue {
+            messages: VecDeque::new(),
+            capacity,
+            path: Some(path.clone()),
+            file_lines: 0,
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    queue.file_lines += 1;
+                    match serde_json::from_str(line) {
+                        Ok(message) => queue.push_back(message),
+                        Err(e) => eprintln!("Skipping invalid queued message: {}", e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(queue)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Appends a message, dropping the oldest one when the queue is full.
+    pub fn push(&mut self, message: QueuedMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(path) = &self.path {
+            if let Err(e) = append_file(path, &message) {
+                eprintln!("Unable to save message queue to {}: {}", path.display(), e);
+            }
+            self.file_lines += 1;
+        }
+        self.push_back(message);
+        if self.file_lines >= 2 * self.capacity {
+            self.save();
+        }
+    }
+
+    /// Sends the queued messages in order until `send` fails, and returns the
+    /// number of messages sent. Unsent messages stay queued.
+    pub fn flush<F>(&mut self, mut send: F) -> usize
+    where
+        F: FnMut(&QueuedMessage) -> bool,
+    {
+        let mut sent = 0;
+        while let Some(message) = self.messages.front() {
+            if !send(message) {
+                break;
+            }
+            self.messages.pop_front();
+            sent += 1;
+        }
+        if sent > 0 {
+            self.save();
+        }
+        sent
+    }
+
+    fn push_back(&mut self, message: QueuedMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    fn save(&mut self) {
+        if let Some(path) = &self.path {
+            match self.write_file(path) {
+                Ok(()) => self.file_lines = self.messages.len(),
+                Err(e) => eprintln!("Unable to save message queue to {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    // Write to a temporary file first so a crash never leaves a truncated queue
+    fn write_file(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        for message in &self.messages {
+            serde_json::to_writer(&mut file, message)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+// A crash may at worst lose the last line, which is skipped on reload
+fn append_file(path: &Path, message: &QueuedMessage) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn message(n: usize) -> QueuedMessage {
+        QueuedMessage {
+            topic: format!("topic/{}", n),
+            payload: format!("{{\"value\":{}}}", n),
+            retained: false,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("srvstat-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_push_drops_oldest_when_full() {
+        let mut queue = MessageQueue::new(2);
+        queue.push(message(1));
+        queue.push(message(2));
+        queue.push(message(3));
+
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.clone());
+            true
+        });
+        assert_eq!(sent, vec![message(2), message(3)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_flush_stops_on_failure() {
+        let mut queue = MessageQueue::new(10);
+        for n in 1..=3 {
+            queue.push(message(n));
+        }
+
+        let mut attempts = 0;
+        let sent = queue.flush(|_| {
+            attempts += 1;
+            attempts < 2
+        });
+        assert_eq!(sent, 1);
+        assert_eq!(queue.len(), 2);
+
+        let mut remaining = Vec::new();
+        queue.flush(|m| {
+            remaining.push(m.clone());
+            true
+        });
+        assert_eq!(remaining, vec![message(2), message(3)]);
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut queue = MessageQueue::new(0);
+        queue.push(message(1));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = temp_path("queue");
+        let _ = fs::remove_file(&path);
+        {
+            let mut queue = MessageQueue::with_file(10, path.clone()).unwrap();
+            queue.push(message(1));
+            queue.push(message(2));
+            queue.push(message(3));
+            queue.flush(|m| m.topic == "topic/1");
+        }
+
+        let mut queue = MessageQueue::with_file(10, path.clone()).unwrap();
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.clone());
+            true
+        });
+        assert_eq!(sent, vec![message(2), message(3)]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_persistence_appends_until_twice_the_capacity() {
+        let path = temp_path("queue-append");
+        let _ = fs::remove_file(&path);
+        let line_count = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+        let mut queue = MessageQueue::with_file(2, path.clone()).unwrap();
+        for n in 1..=3 {
+            queue.push(message(n));
+        }
+        assert_eq!(line_count(&path), 3);
+        queue.push(message(4));
+        assert_eq!(line_count(&path), 2);
+        queue.push(message(5));
+        assert_eq!(line_count(&path), 3);
+
+        let mut queue = MessageQueue::with_file(2, path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.clone());
+            true
+        });
+        assert_eq!(sent, vec![message(4), message(5)]);
+    }
+
+    #[test]
+    fn test_persistence_keeps_retained() {
+        let retained = QueuedMessage {
+            retained: true,
+            ..message(1)
+        };
+        let line = serde_json::to_string(&retained).unwrap();
+        assert_eq!(
+            serde_json::from_str::<QueuedMessage>(&line).unwrap(),
+            retained
+        );
+        // Lines saved before the flag existed are not retained
+        let line = r#"{"topic":"topic/1","payload":"{\"value\":1}"}"#;
+        assert_eq!(
+            serde_json::from_str::<QueuedMessage>(line).unwrap(),
+            message(1)
+        );
+    }
+
+    #[test]
+    fn test_persistence_skips_invalid_lines() {
+        let path = temp_path("queue-invalid");
+        let valid = serde_json::to_string(&message(1)).unwrap();
+        fs::write(&path, format!("not json\n{}\n", valid)).unwrap();
+
+        let queue = MessageQueue::with_file(10, path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_persistence_respects_capacity() {
+        let path = temp_path("queue-capacity");
+        let lines: Vec<String> = (1..=3)
+            .map(|n| serde_json::to_string(&message(n)).unwrap())
+            .collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut queue = MessageQueue::with_file(2, path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut sent = Vec::new();
+        queue.flush(|m| {
+            sent.push(m.clone());
+            true
+        });
+        assert_eq!(sent, vec![message(2), message(3)]);
+    }
+}
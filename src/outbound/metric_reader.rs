@@ -1,108 +1,1413 @@
-use crate::domain::metrics::models::{Category, Metric, Percentage};
-use crate::domain::ports::MetricReader;
-use sysinfo::{Disks, System};
+use crate::config::{Config, SYSFS_ROOT};
+use crate::domain::filter::Filter;
+use crate::domain::metrics::models::{
+    Category, InvalidPercentage, Metric, Percentage, ProcessUsage, Quantity,
+};
+use crate::domain::ports::{MetricError, MetricReader, Snapshot};
+use crate::domain::watch::{ProcessMatcher, WatchedUsage};
+use crate::outbound::cgroup_reader::{CgroupReader, CgroupUsage};
+use crate::outbound::pressure_reader::{self, ResourcePressure};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::{
+    Components, Disks, Networks, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind,
+    MINIMUM_CPU_UPDATE_INTERVAL,
+};
 
 pub struct DummyMetricReader;
 impl MetricReader for DummyMetricReader {
-    fn get_percent(&self, category: &Category) -> Metric {
-        Metric::Percent(
+    fn get_percent(&self, category: &Category) -> Result<Metric, MetricError> {
+        Ok(Metric::Percent(
             "tux".to_string(),
             category.clone(),
-            Percentage::new(25).unwrap(),
-        )
+            Percentage(25),
+        ))
     }
-    fn get_used(&self, category: &Category) -> Metric {
-        Metric::Used("tux".to_string(), category.clone(), 25, 100)
+    fn get_used(&self, category: &Category) -> Result<Metric, MetricError> {
+        Ok(Metric::Used("tux".to_string(), category.clone(), 25, 100))
     }
 }
 
-pub struct SystemMetricReader;
+/// Space usage of a mounted filesystem.
+#[derive(Debug, Clone, PartialEq)]
+struct FilesystemUsage {
+    mount: String,
+    fs_type: String,
+    // Device mounted, e.g. `/dev/sda2`, which bind mounts and btrfs
+    // subvolumes share
+    device: String,
+    used: u64,
+    total: u64,
+}
 
-impl MetricReader for SystemMetricReader {
-    fn get_percent(&self, category: &Category) -> Metric {
-        let mut sys = System::new_all();
-        let host = System::host_name().unwrap();
-        match category {
-            Category::Cpu => {
-                sys.refresh_cpu_usage();
-                let cpu_usage = sys.global_cpu_usage() as u8;
-                Metric::Percent(host, category.clone(), Percentage::new(cpu_usage).unwrap())
-            }
-            _ => {
-                let used = self.get_used(category);
-                match used {
-                    Metric::Used(host, _, used_metric, total_metric) => {
-                        // Calculate the usage percentage
-                        let usage_percent =
-                            1f64 / total_metric as f64 * used_metric as f64 * 100f64;
-                        // Cast the percentage to u8 (after rounding)
-                        let usage_percent_u8 = usage_percent.round() as u8;
-                        let result = Percentage::new(usage_percent_u8);
-                        Metric::Percent(
-                            host.clone(),
-                            category.clone(),
-                            result.unwrap_or_else(|_| {
-                                panic!(
-                                    "{}",
-                                    format!("Erreur poucentage non valide : {}", usage_percent_u8)
-                                        .to_string()
-                                )
-                            }),
-                        )
+/// Traffic of a network interface since the previous refresh, and its error
+/// counters since boot.
+#[derive(Debug, Clone, PartialEq)]
+struct InterfaceUsage {
+    name: String,
+    elapsed: Duration,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    rx_packets: u64,
+    tx_packets: u64,
+    rx_errors: u64,
+    tx_errors: u64,
+    // Only known on Linux
+    rx_dropped: Option<u64>,
+    tx_dropped: Option<u64>,
+}
+
+/// Temperatures of a sensor, in degrees Celsius.
+#[derive(Debug, Clone, PartialEq)]
+struct SensorTemperature {
+    label: String,
+    current: f32,
+    max: Option<f32>,
+    critical: Option<f32>,
+}
+
+/// What a refresh listed besides the [`System`] state.
+#[derive(Debug, Default)]
+struct Refreshed {
+    filesystems: Vec<FilesystemUsage>,
+    interfaces: Vec<InterfaceUsage>,
+    temperatures: Vec<SensorTemperature>,
+    // Top processes by CPU, then by memory
+    processes: Vec<(Category, Vec<ProcessUsage>)>,
+    // Instances of every watched process, by name
+    watched: HashMap<String, WatchedUsage>,
+    cgroups: Vec<CgroupUsage>,
+    pressures: Vec<ResourcePressure>,
+}
+
+/// Reads metrics from a long-lived [`System`], refreshing only what each
+/// category needs.
+///
+/// CPU usage is computed by sysinfo as the difference between two refreshes,
+/// so every CPU reading covers the time elapsed since the previous one (at
+/// least [`MINIMUM_CPU_UPDATE_INTERVAL`]). Per-core values are those of the
+/// last global CPU reading. Network rates likewise cover the time elapsed
+/// since the previous network refresh.
+///
+/// Temperatures come from sysinfo's components, or straight from the hwmon
+/// devices when sysfs is mounted somewhere else than `/sys`.
+///
+/// Processes are only loaded when the processes or watch category is
+/// collected, their CPU usage covering the time since the previous collection.
+/// Cgroup rates likewise cover the time since the previous cgroups reading.
+///
+/// Pressure stall information is read from the procfs root, and is missing on
+/// kernels built without it.
+pub struct SystemMetricReader {
+    sys: Mutex<System>,
+    disks: Mutex<Disks>,
+    networks: Mutex<(Networks, Instant)>,
+    components: Mutex<Components>,
+    // Highest temperature seen per sensor
+    max_temperatures: Mutex<HashMap<String, f32>>,
+    sysfs_root: PathBuf,
+    cpu_refreshed_at: Mutex<Instant>,
+    per_core: bool,
+    mounts: Filter,
+    fs_types: Filter,
+    interfaces: Filter,
+    top_processes: usize,
+    watched: Vec<(String, ProcessMatcher)>,
+    cgroups: CgroupReader,
+    procfs_root: PathBuf,
+}
+
+impl SystemMetricReader {
+    pub fn new(config: &Config) -> Self {
+        let mut sys = System::new();
+        // First CPU refresh, the baseline of the first reading
+        sys.refresh_cpu_usage();
+        let watched: Vec<(String, ProcessMatcher)> = config
+            .watch
+            .iter()
+            .filter_map(|watch| match watch.matcher() {
+                Ok(matcher) => Some((watch.name.clone(), matcher)),
+                Err(e) => {
+                    eprintln!("Ignoring watched process: {:#}", e);
+                    None
+                }
+            })
+            .collect();
+        let categories = config.categories();
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Processes | Category::Watch))
+        {
+            refresh_processes(&mut sys, !watched.is_empty());
+        }
+        let cgroups = CgroupReader::new(&config.cgroups.root, config.cgroups.cgroup_filter());
+        if categories.contains(&Category::Cgroups) {
+            // First cgroups reading, the baseline of the first rates
+            cgroups.read();
+        }
+        let procfs_root = &config.collection.procfs_root;
+        if categories.contains(&Category::Pressure) && !pressure_reader::is_available(procfs_root) {
+            eprintln!(
+                "Pressure stall information is not available in {}, the kernel needs CONFIG_PSI",
+                procfs_root.display()
+            );
+        }
+        SystemMetricReader {
+            sys: Mutex::new(sys),
+            disks: Mutex::new(Disks::new()),
+            // First network refresh, the baseline of the first rates
+            networks: Mutex::new((Networks::new_with_refreshed_list(), Instant::now())),
+            components: Mutex::new(Components::new()),
+            max_temperatures: Mutex::new(HashMap::new()),
+            sysfs_root: config.collection.sysfs_root.clone(),
+            cpu_refreshed_at: Mutex::new(Instant::now()),
+            per_core: config.cpu.per_core,
+            mounts: config.disk.mount_filter(),
+            fs_types: config.disk.fs_type_filter(),
+            interfaces: config.network.interface_filter(),
+            top_processes: config.processes.top,
+            watched,
+            cgroups,
+            procfs_root: procfs_root.clone(),
+        }
+    }
+
+    fn sys(&self) -> MutexGuard<'_, System> {
+        self.sys.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Refresh the CPU usage, waiting if the previous refresh is too recent to
+    // give a meaningful value
+    fn refresh_cpu(&self, sys: &mut System) {
+        let mut refreshed_at = self
+            .cpu_refreshed_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let elapsed = refreshed_at.elapsed();
+        if elapsed < MINIMUM_CPU_UPDATE_INTERVAL {
+            thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL - elapsed);
+        }
+        sys.refresh_cpu_usage();
+        *refreshed_at = Instant::now();
+    }
+
+    // Every mounted filesystem passing the mount and type filters
+    fn filesystems(&self) -> Vec<FilesystemUsage> {
+        let mut disks = self.disks.lock().unwrap_or_else(|e| e.into_inner());
+        disks.refresh(true);
+        let all = disks.iter().map(|disk| FilesystemUsage {
+            mount: disk.mount_point().to_string_lossy().into_owned(),
+            fs_type: disk.file_system().to_string_lossy().into_owned(),
+            device: disk.name().to_string_lossy().into_owned(),
+            used: disk.total_space().saturating_sub(disk.available_space()),
+            total: disk.total_space(),
+        });
+        self.select(all)
+    }
+
+    // Every network interface passing the filters, with its traffic since the
+    // previous call
+    fn interfaces(&self) -> Vec<InterfaceUsage> {
+        let mut guard = self.networks.lock().unwrap_or_else(|e| e.into_inner());
+        let (networks, refreshed_at) = &mut *guard;
+        let elapsed = refreshed_at.elapsed();
+        networks.refresh(true);
+        *refreshed_at = Instant::now();
+        let mut interfaces: Vec<InterfaceUsage> = networks
+            .iter()
+            .filter(|(name, _)| self.interfaces.allows(name))
+            .map(|(name, data)| InterfaceUsage {
+                name: name.clone(),
+                elapsed,
+                rx_bytes: data.received(),
+                tx_bytes: data.transmitted(),
+                rx_packets: data.packets_received(),
+                tx_packets: data.packets_transmitted(),
+                rx_errors: data.total_errors_on_received(),
+                tx_errors: data.total_errors_on_transmitted(),
+                rx_dropped: dropped(&self.sysfs_root, name, "rx"),
+                tx_dropped: dropped(&self.sysfs_root, name, "tx"),
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        interfaces
+    }
+
+    // Every temperature sensor with a current value
+    fn temperatures(&self) -> Vec<SensorTemperature> {
+        let temperatures = if self.sysfs_root == Path::new(SYSFS_ROOT) {
+            self.component_temperatures()
+        } else {
+            hwmon_temperatures(&self.sysfs_root)
+        };
+        let mut max_temperatures = self
+            .max_temperatures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut temperatures: Vec<SensorTemperature> = temperatures
+            .into_iter()
+            .map(|mut sensor| {
+                let max = max_temperatures
+                    .entry(sensor.label.clone())
+                    .or_insert(sensor.current);
+                *max = max.max(sensor.current).max(sensor.max.unwrap_or(f32::MIN));
+                sensor.max = Some(*max);
+                sensor
+            })
+            .collect();
+        temperatures.sort_by(|a, b| a.label.cmp(&b.label));
+        temperatures
+    }
+
+    // sysinfo ids hwmon inputs as `hwmon<N>_<input>`, which tells the device
+    // of the sensors sharing a label
+    fn component_temperatures(&self) -> Vec<SensorTemperature> {
+        let mut components = self.components.lock().unwrap_or_else(|e| e.into_inner());
+        components.refresh(true);
+        let temperatures = components
+            .iter()
+            .filter_map(|component| {
+                let id = component.id().unwrap_or_default();
+                let device = match id.split_once('_') {
+                    Some((hwmon, _)) if hwmon.starts_with("hwmon") => {
+                        device_id(&self.sysfs_root.join("class/hwmon").join(hwmon))
+                    }
+                    _ => id.to_string(),
+                };
+                let sensor = SensorTemperature {
+                    label: component.label().to_string(),
+                    current: component.temperature()?,
+                    max: component.max(),
+                    critical: component.critical(),
+                };
+                Some((device, sensor))
+            })
+            .collect();
+        disambiguate(temperatures)
+    }
+
+    // The processes using the most CPU, then those using the most memory
+    fn processes(&self, sys: &System) -> Vec<(Category, Vec<ProcessUsage>)> {
+        let processes: Vec<ProcessUsage> = sys
+            .processes()
+            .iter()
+            .map(|(pid, process)| ProcessUsage {
+                name: process.name().to_string_lossy().into_owned(),
+                pid: pid.as_u32(),
+                cpu: process.cpu_usage(),
+                memory: process.memory(),
+            })
+            .collect();
+        [Category::Cpu, Category::Memory]
+            .into_iter()
+            .map(|by| {
+                let top = ProcessUsage::top(processes.clone(), &by, self.top_processes);
+                (by, top)
+            })
+            .collect()
+    }
+
+    // The running instances of every watched process, even when there are none
+    fn watched(&self, sys: &System) -> HashMap<String, WatchedUsage> {
+        let mut watched: HashMap<String, WatchedUsage> = self
+            .watched
+            .iter()
+            .map(|(name, _)| (name.clone(), WatchedUsage::default()))
+            .collect();
+        for process in sys.processes().values() {
+            let name = process.name().to_string_lossy();
+            let cmdline = process
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ");
+            for (watch, matcher) in &self.watched {
+                if matcher.matches(&name, process.exe(), &cmdline) {
+                    if let Some(usage) = watched.get_mut(watch) {
+                        usage.add(process.cpu_usage(), process.memory());
                     }
-                    _ => unreachable!(),
                 }
             }
         }
+        watched
     }
 
-    fn get_used(&self, category: &Category) -> Metric {
-        // Initialize the system info struct
-        let mut sys = System::new_all();
-        let host = System::host_name().unwrap();
+    // Keep the filesystems passing the filters, once per mount point
+    fn select(&self, filesystems: impl Iterator<Item = FilesystemUsage>) -> Vec<FilesystemUsage> {
+        let mut seen = HashSet::new();
+        filesystems
+            .filter(|fs| self.mounts.allows(&fs.mount) && self.fs_types.allows(&fs.fs_type))
+            .filter(|fs| seen.insert(fs.mount.clone()))
+            .collect()
+    }
+}
+
+impl SystemMetricReader {
+    // Categories reported for `category`, given the selected filesystems and
+    // interfaces, and the number of cores
+    fn expand_with(
+        &self,
+        category: &Category,
+        refreshed: &Refreshed,
+        cores: usize,
+    ) -> Vec<Category> {
         match category {
-            Category::Disk => {
-                // Refresh system data to ensure we get the latest info
-                sys.refresh_all();
+            Category::Disk => std::iter::once(Category::Disk)
+                .chain(
+                    refreshed
+                        .filesystems
+                        .iter()
+                        .map(|fs| Category::Filesystem(fs.mount.clone())),
+                )
+                .collect(),
+            // Interfaces and sensors have no total
+            Category::Network => refreshed
+                .interfaces
+                .iter()
+                .map(|interface| Category::Interface(interface.name.clone()))
+                .collect(),
+            Category::Temperature => refreshed
+                .temperatures
+                .iter()
+                .map(|sensor| Category::Sensor(sensor.label.clone()))
+                .collect(),
+            Category::Watch => self
+                .watched
+                .iter()
+                .map(|(name, _)| Category::Watched(name.clone()))
+                .collect(),
+            Category::Cgroups => refreshed
+                .cgroups
+                .iter()
+                .map(|cgroup| Category::Cgroup(cgroup.path.clone()))
+                .collect(),
+            Category::Pressure => refreshed
+                .pressures
+                .iter()
+                .map(|pressure| Category::Psi(pressure.resource.clone()))
+                .collect(),
+            Category::Cpu if self.per_core => std::iter::once(Category::Cpu)
+                .chain((0..cores).map(Category::Core))
+                .collect(),
+            _ => vec![category.clone()],
+        }
+    }
 
-                // Get the first disk
-                let disks = Disks::new_with_refreshed_list();
-                let disk = disks.first();
-                let total_space = disk.unwrap().total_space();
-                let available_space = disk.unwrap().available_space();
+    // Refresh what `categories` need: cores reuse the values of the last CPU
+    // refresh, and filesystems, interfaces, sensors, processes, cgroups and
+    // pressures are only listed for the categories reporting them
+    fn refresh(&self, sys: &mut System, categories: &[Category]) -> Refreshed {
+        if categories.contains(&Category::Cpu) {
+            self.refresh_cpu(sys);
+        }
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Memory | Category::Swap))
+        {
+            sys.refresh_memory();
+        }
+        let mut refreshed = Refreshed::default();
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Disk | Category::Filesystem(_)))
+        {
+            refreshed.filesystems = self.filesystems();
+        }
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Network | Category::Interface(_)))
+        {
+            refreshed.interfaces = self.interfaces();
+        }
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Temperature | Category::Sensor(_)))
+        {
+            refreshed.temperatures = self.temperatures();
+        }
+        let watch = categories
+            .iter()
+            .any(|c| matches!(c, Category::Watch | Category::Watched(_)));
+        if watch || categories.contains(&Category::Processes) {
+            refresh_processes(sys, watch);
+        }
+        if categories.contains(&Category::Processes) {
+            refreshed.processes = self.processes(sys);
+        }
+        if watch {
+            refreshed.watched = self.watched(sys);
+        }
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Cgroups | Category::Cgroup(_)))
+        {
+            refreshed.cgroups = self.cgroups.read();
+        }
+        if categories
+            .iter()
+            .any(|c| matches!(c, Category::Pressure | Category::Psi(_)))
+        {
+            refreshed.pressures = pressure_reader::read_pressure(&self.procfs_root);
+        }
+        refreshed
+    }
+}
+
+impl MetricReader for SystemMetricReader {
+    fn expand(&self, category: &Category) -> Vec<Category> {
+        let refreshed = match category {
+            Category::Disk => Refreshed {
+                filesystems: self.filesystems(),
+                ..Refreshed::default()
+            },
+            Category::Network => Refreshed {
+                interfaces: self.interfaces(),
+                ..Refreshed::default()
+            },
+            Category::Temperature => Refreshed {
+                temperatures: self.temperatures(),
+                ..Refreshed::default()
+            },
+            Category::Cgroups => Refreshed {
+                cgroups: self.cgroups.read(),
+                ..Refreshed::default()
+            },
+            Category::Pressure => Refreshed {
+                pressures: pressure_reader::read_pressure(&self.procfs_root),
+                ..Refreshed::default()
+            },
+            _ => Refreshed::default(),
+        };
+        let cores = self.sys().cpus().len();
+        self.expand_with(category, &refreshed, cores)
+    }
+
+    fn get_percent(&self, category: &Category) -> Result<Metric, MetricError> {
+        let mut sys = self.sys();
+        let refreshed = self.refresh(&mut sys, std::slice::from_ref(category));
+        Readings::new(&sys, &refreshed)?.percent(category)
+    }
+
+    fn get_used(&self, category: &Category) -> Result<Metric, MetricError> {
+        let mut sys = self.sys();
+        let refreshed = self.refresh(&mut sys, std::slice::from_ref(category));
+        Readings::new(&sys, &refreshed)?.used(category)
+    }
 
-                // Calculate used space
-                let used_space = total_space - available_space;
+    fn get_values(&self, category: &Category) -> Result<Vec<Metric>, MetricError> {
+        let mut sys = self.sys();
+        let refreshed = self.refresh(&mut sys, std::slice::from_ref(category));
+        Readings::new(&sys, &refreshed)?.values(category)
+    }
 
-                Metric::Used(host, category.clone(), used_space, total_space)
+    // Refresh everything once, then read every metric from that state
+    fn collect(&self, categories: &[Category]) -> Snapshot {
+        let mut sys = self.sys();
+        let refreshed = self.refresh(&mut sys, categories);
+        let mut snapshot = Snapshot::new();
+        let readings = match Readings::new(&sys, &refreshed) {
+            Ok(readings) => readings,
+            Err(e) => {
+                snapshot.failures.push(e);
+                return snapshot;
             }
-            Category::Memory => {
-                // Refresh system data to ensure we get the latest info
-                sys.refresh_memory();
-                Metric::Used(
-                    host,
-                    category.clone(),
-                    sys.used_memory(),
-                    sys.total_memory(),
-                )
+        };
+        for category in categories {
+            for category in self.expand_with(category, &refreshed, sys.cpus().len()) {
+                if !category.is_usage() {
+                    match readings.values(&category) {
+                        Ok(metrics) => snapshot.metrics.extend(metrics),
+                        Err(e) => snapshot.failures.push(e),
+                    }
+                    continue;
+                }
+                snapshot.push(readings.percent(&category));
+                if !category.is_cpu() {
+                    snapshot.push(readings.used(&category));
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+/// Metrics computed from already refreshed system data.
+struct Readings<'a> {
+    host: String,
+    sys: &'a System,
+    filesystems: &'a [FilesystemUsage],
+    interfaces: &'a [InterfaceUsage],
+    temperatures: &'a [SensorTemperature],
+    processes: &'a [(Category, Vec<ProcessUsage>)],
+    watched: &'a HashMap<String, WatchedUsage>,
+    cgroups: &'a [CgroupUsage],
+    pressures: &'a [ResourcePressure],
+}
+
+impl<'a> Readings<'a> {
+    fn new(sys: &'a System, refreshed: &'a Refreshed) -> Result<Self, MetricError> {
+        Ok(Readings {
+            host: host()?,
+            sys,
+            filesystems: &refreshed.filesystems,
+            interfaces: &refreshed.interfaces,
+            temperatures: &refreshed.temperatures,
+            processes: &refreshed.processes,
+            watched: &refreshed.watched,
+            cgroups: &refreshed.cgroups,
+            pressures: &refreshed.pressures,
+        })
+    }
+
+    fn percent(&self, category: &Category) -> Result<Metric, MetricError> {
+        let percentage = match category {
+            Category::Cpu => cpu_percent(self.sys.global_cpu_usage()),
+            Category::Core(index) => self
+                .sys
+                .cpus()
+                .get(*index)
+                .map(|cpu| cpu_percent(cpu.cpu_usage()))
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            _ => {
+                let (used, total) = self.used_total(category)?;
+                used_percent(used, total).map_err(|source| MetricError::InvalidPercentage {
+                    category: category.clone(),
+                    source,
+                })?
             }
-            Category::Cpu => {
-                // error no used metric for cpu
-                eprintln!("Error: no used metric for cpu");
-                Metric::Used(host, category.clone(), 0, 0)
+        };
+        Ok(Metric::Percent(
+            self.host.clone(),
+            category.clone(),
+            percentage,
+        ))
+    }
+
+    fn used(&self, category: &Category) -> Result<Metric, MetricError> {
+        let (used, total) = self.used_total(category)?;
+        Ok(Metric::Used(
+            self.host.clone(),
+            category.clone(),
+            used,
+            total,
+        ))
+    }
+
+    fn used_total(&self, category: &Category) -> Result<(u64, u64), MetricError> {
+        match category {
+            // Sum the space of every reported device
+            Category::Disk => Ok(disk_total(self.filesystems)),
+            Category::Filesystem(mount) => self
+                .filesystems
+                .iter()
+                .find(|fs| &fs.mount == mount)
+                .map(|fs| (fs.used, fs.total))
+                .ok_or_else(|| MetricError::Unavailable(category.clone())),
+            Category::Memory => Ok((self.sys.used_memory(), self.sys.total_memory())),
+            Category::Swap => Ok((self.sys.used_swap(), self.sys.total_swap())),
+            _ => Err(MetricError::Unsupported(category.clone())),
+        }
+    }
+
+    // Load averages and uptime need no refresh
+    fn values(&self, category: &Category) -> Result<Vec<Metric>, MetricError> {
+        let values = match category {
+            Category::Load => {
+                let load = System::load_average();
+                vec![
+                    (Quantity::Load1, load.one),
+                    (Quantity::Load5, load.five),
+                    (Quantity::Load15, load.fifteen),
+                ]
             }
-            Category::Swap => {
-                // sys and host are already defined in the outer scope of this match
-                sys.refresh_memory(); // refresh memory info
-                let used_swap = sys.used_swap(); // get used swap
-                let total_swap = sys.total_swap(); // get total swap
-                Metric::Used(
-                    host,
-                    category.clone(),
-                    used_swap,
-                    total_swap,
-                )
+            Category::Uptime => vec![
+                (Quantity::Uptime, System::uptime() as f64),
+                (Quantity::BootTime, System::boot_time() as f64),
+            ],
+            Category::Interface(name) => self
+                .interfaces
+                .iter()
+                .find(|interface| &interface.name == name)
+                .map(interface_values)
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            Category::Sensor(label) => self
+                .temperatures
+                .iter()
+                .find(|sensor| &sensor.label == label)
+                .map(temperature_values)
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            Category::Watched(name) => self
+                .watched
+                .get(name)
+                .map(watched_values)
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            Category::Cgroup(path) => self
+                .cgroups
+                .iter()
+                .find(|cgroup| &cgroup.path == path)
+                .map(CgroupUsage::values)
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            Category::Psi(resource) => self
+                .pressures
+                .iter()
+                .find(|pressure| &pressure.resource == resource)
+                .map(ResourcePressure::values)
+                .ok_or_else(|| MetricError::Unavailable(category.clone()))?,
+            Category::Processes => {
+                return Ok(self
+                    .processes
+                    .iter()
+                    .map(|(by, top)| Metric::Top(self.host.clone(), by.clone(), top.clone()))
+                    .collect())
             }
+            _ => return Err(MetricError::Unsupported(category.clone())),
+        };
+        Ok(values
+            .into_iter()
+            .map(|(quantity, value)| {
+                Metric::Value(self.host.clone(), category.clone(), quantity, value)
+            })
+            .collect())
+    }
+}
+
+// Refresh the CPU and memory usage of every process, forgetting those that
+// exited, and their executable and command line for matching watched ones.
+// Threads are left out, their usage being that of their process
+fn refresh_processes(sys: &mut System, watch: bool) {
+    let mut kind = ProcessRefreshKind::nothing()
+        .with_cpu()
+        .with_memory()
+        .without_tasks();
+    if watch {
+        kind = kind
+            .with_exe(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet);
+    }
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+}
+
+// Whether a watched process runs, then its instances and their usage
+fn watched_values(usage: &WatchedUsage) -> Vec<(Quantity, f64)> {
+    vec![
+        (Quantity::Running, f64::from(u8::from(usage.instances > 0))),
+        (Quantity::Instances, usage.instances as f64),
+        (
+            Quantity::ProcessCpu,
+            (f64::from(usage.cpu) * 10.0).round() / 10.0,
+        ),
+        (Quantity::ProcessMemory, usage.memory as f64),
+    ]
+}
+
+// Rates over the time since the previous refresh, then the counters
+fn interface_values(interface: &InterfaceUsage) -> Vec<(Quantity, f64)> {
+    let seconds = interface.elapsed.as_secs_f64();
+    let rate = |count: u64| {
+        if seconds > 0.0 {
+            (count as f64 / seconds * 100.0).round() / 100.0
+        } else {
+            0.0
         }
+    };
+    let mut values = vec![
+        (Quantity::RxBytes, rate(interface.rx_bytes)),
+        (Quantity::TxBytes, rate(interface.tx_bytes)),
+        (Quantity::RxPackets, rate(interface.rx_packets)),
+        (Quantity::TxPackets, rate(interface.tx_packets)),
+        (Quantity::RxErrors, interface.rx_errors as f64),
+        (Quantity::TxErrors, interface.tx_errors as f64),
+    ];
+    if let Some(dropped) = interface.rx_dropped {
+        values.push((Quantity::RxDropped, dropped as f64));
+    }
+    if let Some(dropped) = interface.tx_dropped {
+        values.push((Quantity::TxDropped, dropped as f64));
+    }
+    values
+}
+
+// Packets dropped by an interface in a direction (`rx` or `tx`), from sysfs
+fn dropped(sysfs_root: &Path, name: &str, direction: &str) -> Option<u64> {
+    let path = sysfs_root
+        .join("class/net")
+        .join(name)
+        .join(format!("statistics/{}_dropped", direction));
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// Temperatures rounded to a tenth of a degree, thresholds only when known
+fn temperature_values(sensor: &SensorTemperature) -> Vec<(Quantity, f64)> {
+    let celsius = |value: f32| (f64::from(value) * 10.0).round() / 10.0;
+    let mut values = vec![(Quantity::Temperature, celsius(sensor.current))];
+    if let Some(max) = sensor.max {
+        values.push((Quantity::TemperatureMax, celsius(max)));
+    }
+    if let Some(critical) = sensor.critical {
+        values.push((Quantity::TemperatureCritical, celsius(critical)));
+    }
+    values
+}
+
+/// Reads the temperature inputs of every hwmon device under `sysfs_root`,
+/// labelled like sysinfo does: the device name, then the input label or
+/// `temp<N>`. Labels shared by several devices, such as the `nvme Composite`
+/// of two drives, are followed by the device (e.g. `nvme Composite 0000:02:00.0`).
+fn hwmon_temperatures(sysfs_root: &Path) -> Vec<SensorTemperature> {
+    let Ok(devices) = fs::read_dir(sysfs_root.join("class/hwmon")) else {
+        return Vec::new();
+    };
+    let mut devices: Vec<PathBuf> = devices.flatten().map(|device| device.path()).collect();
+    devices.sort();
+    // Sensors with the device they belong to
+    let mut temperatures = Vec::new();
+    for folder in devices {
+        let device = device_id(&folder);
+        let name = read_line(&folder.join("name")).unwrap_or_default();
+        let Ok(files) = fs::read_dir(&folder) else {
+            continue;
+        };
+        let mut inputs: Vec<u32> = files
+            .flatten()
+            .filter_map(|file| {
+                let file_name = file.file_name();
+                let id = file_name.to_str()?.strip_prefix("temp")?;
+                id.strip_suffix("_input")?.parse().ok()
+            })
+            .collect();
+        inputs.sort_unstable();
+        for id in inputs {
+            let file = |item: &str| folder.join(format!("temp{}_{}", id, item));
+            let Some(current) = read_celsius(&file("input")) else {
+                continue;
+            };
+            let label = match read_line(&file("label")) {
+                Some(label) => format!("{} {}", name, label),
+                None => format!("{} temp{}", name, id),
+            };
+            let sensor = SensorTemperature {
+                label,
+                current,
+                max: read_celsius(&file("highest")),
+                critical: read_celsius(&file("crit")),
+            };
+            temperatures.push((device.clone(), sensor));
+        }
+    }
+    disambiguate(temperatures)
+}
+
+// Follow the sensors' labels by their device when several share one
+fn disambiguate(temperatures: Vec<(String, SensorTemperature)>) -> Vec<SensorTemperature> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (_, sensor) in &temperatures {
+        *counts.entry(sensor.label.clone()).or_default() += 1;
+    }
+    temperatures
+        .into_iter()
+        .map(|(device, mut sensor)| {
+            if counts[&sensor.label] > 1 {
+                sensor.label = format!("{} {}", sensor.label, device);
+            }
+            sensor
+        })
+        .collect()
+}
+
+/// Identifies the device of a hwmon folder across reboots, unlike the
+/// `hwmon<N>` numbering: the `device` links are followed down to the bus
+/// device, e.g. the PCI address `0000:02:00.0` of an NVMe drive rather than
+/// its `nvme0` controller. Falls back to the folder name without a device.
+fn device_id(folder: &Path) -> String {
+    let mut device = None;
+    let mut link = folder.join("device");
+    while link.is_symlink() {
+        let Ok(target) = fs::canonicalize(&link) else {
+            break;
+        };
+        link = target.join("device");
+        device = Some(target);
+    }
+    device
+        .as_deref()
+        .unwrap_or(folder)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Used and total space of the filesystems, counting each device once. Only
+// device paths are compared: virtual filesystems, such as every `tmpfs`,
+// share a name without sharing their space
+fn disk_total(filesystems: &[FilesystemUsage]) -> (u64, u64) {
+    let mut devices = HashSet::new();
+    filesystems
+        .iter()
+        .filter(|fs| !fs.device.starts_with('/') || devices.insert(fs.device.as_str()))
+        .fold((0, 0), |(used, total), fs| {
+            (used + fs.used, total + fs.total)
+        })
+}
+
+fn read_line(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    Some(content.trim_end().to_string()).filter(|line| !line.is_empty())
+}
+
+// hwmon temperatures are in millidegrees Celsius
+fn read_celsius(path: &Path) -> Option<f32> {
+    let millidegrees: i32 = read_line(path)?.parse().ok()?;
+    Some(millidegrees as f32 / 1000.0)
+}
+
+fn host() -> Result<String, MetricError> {
+    System::host_name().ok_or(MetricError::HostName)
+}
+
+// Nothing used out of nothing (e.g. no swap) is reported as 0%
+fn used_percent(used: u64, total: u64) -> Result<Percentage, InvalidPercentage> {
+    if total == 0 {
+        return Ok(Percentage(0));
+    }
+    let percent = (used as f64 / total as f64 * 100.0).round();
+    Percentage::new(percent.min(u8::MAX as f64) as u8)
+}
+
+// sysinfo may report slightly above 100% on busy systems
+fn cpu_percent(usage: f32) -> Percentage {
+    Percentage(usage.round().clamp(0.0, 100.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatchConfig;
+
+    fn fs(mount: &str, fs_type: &str) -> FilesystemUsage {
+        FilesystemUsage {
+            mount: mount.to_string(),
+            fs_type: fs_type.to_string(),
+            device: String::new(),
+            used: 25,
+            total: 100,
+        }
+    }
+
+    fn mounts(filesystems: Vec<FilesystemUsage>) -> Vec<String> {
+        filesystems.into_iter().map(|fs| fs.mount).collect()
+    }
+
+    #[test]
+    fn test_select_default_filters() {
+        let reader = SystemMetricReader::new(&Config::default());
+        let selected = reader.select(
+            vec![
+                fs("/", "ext4"),
+                fs("/run", "tmpfs"),
+                fs("/var/lib/docker/overlay2/abc/merged", "overlay"),
+                fs("/snap/core/1", "squashfs"),
+                fs("/home", "btrfs"),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(mounts(selected), vec!["/", "/home"]);
+    }
+
+    #[test]
+    fn test_select_mount_filters() {
+        let mut config = Config::default();
+        config.disk.include_mounts = vec!["/".to_string(), "/mnt/*".to_string()];
+        config.disk.exclude_mounts = vec!["/mnt/backup".to_string()];
+        let reader = SystemMetricReader::new(&config);
+        let selected = reader.select(
+            vec![
+                fs("/", "ext4"),
+                fs("/home", "ext4"),
+                fs("/mnt/data", "xfs"),
+                fs("/mnt/backup", "xfs"),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(mounts(selected), vec!["/", "/mnt/data"]);
+    }
+
+    #[test]
+    fn test_select_deduplicates_mounts() {
+        let reader = SystemMetricReader::new(&Config::default());
+        let selected = reader.select(vec![fs("/", "ext4"), fs("/", "ext4")].into_iter());
+        assert_eq!(mounts(selected), vec!["/"]);
+    }
+
+    #[test]
+    fn test_disk_total_counts_devices_once() {
+        let on = |mount: &str, device: &str| FilesystemUsage {
+            device: device.to_string(),
+            ..fs(mount, "btrfs")
+        };
+        let filesystems = [
+            on("/", "/dev/sda2"),
+            on("/home", "/dev/sda2"),
+            on("/boot", "/dev/sda1"),
+            on("/run", "tmpfs"),
+            on("/tmp", "tmpfs"),
+        ];
+        assert_eq!(disk_total(&filesystems), (100, 400));
+    }
+
+    #[test]
+    fn test_no_filesystem() {
+        let mut config = Config::default();
+        config.disk.include_mounts = vec!["/nonexistent/*".to_string()];
+        let reader = SystemMetricReader::new(&config);
+        assert_eq!(reader.expand(&Category::Disk), vec![Category::Disk]);
+        assert!(matches!(
+            reader.get_used(&Category::Disk),
+            Ok(Metric::Used(_, Category::Disk, 0, 0))
+        ));
+        assert!(matches!(
+            reader.get_percent(&Category::Disk),
+            Ok(Metric::Percent(_, Category::Disk, Percentage(0)))
+        ));
+        assert!(matches!(
+            reader.get_used(&Category::Filesystem("/nonexistent/data".to_string())),
+            Err(MetricError::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_used_percent() {
+        assert_eq!(used_percent(25, 100), Ok(Percentage(25)));
+        assert_eq!(used_percent(2, 3), Ok(Percentage(67)));
+        assert_eq!(used_percent(0, 0), Ok(Percentage(0)));
+        assert_eq!(used_percent(300, 100), Err(InvalidPercentage));
+    }
+
+    #[test]
+    fn test_cpu_has_no_used_value() {
+        let reader = SystemMetricReader::new(&Config::default());
+        assert!(matches!(
+            reader.get_used(&Category::Cpu),
+            Err(MetricError::Unsupported(Category::Cpu))
+        ));
+        assert!(matches!(
+            reader.get_percent(&Category::Core(usize::MAX)),
+            Err(MetricError::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut config = Config::default();
+        config.cpu.per_core = false;
+        let reader = SystemMetricReader::new(&config);
+        let snapshot = reader.collect(&[Category::Cpu, Category::Memory, Category::Swap]);
+
+        assert!(snapshot.failures.is_empty());
+        let categories: Vec<&Category> = snapshot
+            .metrics
+            .iter()
+            .map(|metric| match metric {
+                Metric::Percent(_, category, _)
+                | Metric::Used(_, category, _, _)
+                | Metric::Window(_, category, _)
+                | Metric::Value(_, category, _, _)
+                | Metric::Top(_, category, _) => category,
+            })
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                &Category::Cpu,
+                &Category::Memory,
+                &Category::Memory,
+                &Category::Swap,
+                &Category::Swap
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_values() {
+        let reader = SystemMetricReader::new(&Config::default());
+        let snapshot = reader.collect(&[Category::Load, Category::Uptime]);
+
+        assert!(snapshot.failures.is_empty());
+        let quantities: Vec<(&Category, Quantity)> = snapshot
+            .metrics
+            .iter()
+            .filter_map(|metric| match metric {
+                Metric::Value(_, category, quantity, _) => Some((category, *quantity)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            quantities,
+            vec![
+                (&Category::Load, Quantity::Load1),
+                (&Category::Load, Quantity::Load5),
+                (&Category::Load, Quantity::Load15),
+                (&Category::Uptime, Quantity::Uptime),
+                (&Category::Uptime, Quantity::BootTime),
+            ]
+        );
+        assert!(matches!(
+            reader.get_percent(&Category::Load),
+            Err(MetricError::Unsupported(Category::Load))
+        ));
+    }
+
+    #[test]
+    fn test_collect_processes() {
+        let mut config = Config::default();
+        config.collection.categories = vec![Category::Processes];
+        config.processes.top = 3;
+        let reader = SystemMetricReader::new(&config);
+        let snapshot = reader.collect(&[Category::Processes]);
+
+        assert!(snapshot.failures.is_empty());
+        let rankings: Vec<(&Category, &Vec<ProcessUsage>)> = snapshot
+            .metrics
+            .iter()
+            .map(|metric| match metric {
+                Metric::Top(_, by, top) => (by, top),
+                _ => panic!("unexpected metric {:?}", metric),
+            })
+            .collect();
+        assert_eq!(rankings.len(), 2);
+        let (by, top) = rankings[1];
+        assert_eq!(by, &Category::Memory);
+        assert!(!top.is_empty() && top.len() <= 3);
+        assert!(top[0].memory > 0);
+        assert!(top.windows(2).all(|pair| pair[0].memory >= pair[1].memory));
+        assert_eq!(rankings[0].0, &Category::Cpu);
+    }
+
+    #[test]
+    fn test_collect_watched() {
+        let exe = std::env::current_exe().unwrap();
+        let config = Config {
+            watch: vec![
+                WatchConfig {
+                    name: "tests".to_string(),
+                    process: None,
+                    exe: Some(exe.to_string_lossy().into_owned()),
+                    cmdline: None,
+                },
+                WatchConfig {
+                    name: "missing".to_string(),
+                    process: Some("srvstat-nonexistent".to_string()),
+                    exe: None,
+                    cmdline: None,
+                },
+            ],
+            ..Config::default()
+        };
+        let reader = SystemMetricReader::new(&config);
+        let snapshot = reader.collect(&[Category::Watch]);
+
+        assert!(snapshot.failures.is_empty());
+        let values: HashMap<(String, Quantity), f64> = snapshot
+            .metrics
+            .iter()
+            .map(|metric| match metric {
+                Metric::Value(_, Category::Watched(name), quantity, value) => {
+                    ((name.clone(), *quantity), *value)
+                }
+                _ => panic!("unexpected metric {:?}", metric),
+            })
+            .collect();
+        let value = |name: &str, quantity| values[&(name.to_string(), quantity)];
+        assert_eq!(value("tests", Quantity::Running), 1.0);
+        assert!(value("tests", Quantity::Instances) >= 1.0);
+        assert!(value("tests", Quantity::ProcessMemory) > 0.0);
+        assert_eq!(value("missing", Quantity::Running), 0.0);
+        assert_eq!(value("missing", Quantity::Instances), 0.0);
+        assert_eq!(value("missing", Quantity::ProcessCpu), 0.0);
+    }
+
+    #[test]
+    fn test_collect_cgroups() {
+        let root = std::env::temp_dir().join(format!("srvstat-cgroups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docker/1a2b")).unwrap();
+        fs::write(root.join("docker/1a2b/memory.current"), "1024\n").unwrap();
+        fs::write(root.join("docker/1a2b/memory.max"), "4096\n").unwrap();
+        let mut config = Config::default();
+        config.collection.categories = vec![Category::Cgroups];
+        config.cgroups.root = root.clone();
+        config.cgroups.exclude_cgroups = vec!["docker".to_string()];
+        let reader = SystemMetricReader::new(&config);
+        let snapshot = reader.collect(&[Category::Cgroups]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(snapshot.failures.is_empty());
+        let cgroup = Category::Cgroup("docker/1a2b".to_string());
+        assert_eq!(
+            snapshot.metrics,
+            vec![
+                Metric::Value(
+                    host().unwrap(),
+                    cgroup.clone(),
+                    Quantity::MemoryUsed,
+                    1024.0
+                ),
+                Metric::Value(host().unwrap(), cgroup.clone(), Quantity::MemoryMax, 4096.0),
+                Metric::Value(host().unwrap(), cgroup, Quantity::MemoryUsage, 25.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_pressure() {
+        let root = std::env::temp_dir().join(format!("srvstat-procfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("pressure")).unwrap();
+        fs::write(
+            root.join("pressure/cpu"),
+            "some avg10=1.50 avg60=0.50 avg300=0.10 total=1000\n",
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.collection.categories = vec![Category::Pressure];
+        config.collection.procfs_root = root.clone();
+        let reader = SystemMetricReader::new(&config);
+        assert_eq!(
+            reader.expand(&Category::Pressure),
+            vec![Category::Psi("cpu".to_string())]
+        );
+        let snapshot = reader.collect(&[Category::Pressure]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(snapshot.failures.is_empty());
+        let cpu = Category::Psi("cpu".to_string());
+        assert_eq!(
+            snapshot.metrics,
+            vec![
+                Metric::Value(host().unwrap(), cpu.clone(), Quantity::SomeAvg10, 1.5),
+                Metric::Value(host().unwrap(), cpu.clone(), Quantity::SomeAvg60, 0.5),
+                Metric::Value(host().unwrap(), cpu, Quantity::SomeAvg300, 0.1),
+            ]
+        );
+
+        // Without PSI, nothing is reported
+        assert!(reader.collect(&[Category::Pressure]).metrics.is_empty());
+    }
+
+    #[test]
+    fn test_interface_values() {
+        let mut interface = InterfaceUsage {
+            name: "eth0".to_string(),
+            elapsed: Duration::from_secs(4),
+            rx_bytes: 4000,
+            tx_bytes: 10,
+            rx_packets: 40,
+            tx_packets: 2,
+            rx_errors: 3,
+            tx_errors: 0,
+            rx_dropped: Some(7),
+            tx_dropped: None,
+        };
+        assert_eq!(
+            interface_values(&interface),
+            vec![
+                (Quantity::RxBytes, 1000.0),
+                (Quantity::TxBytes, 2.5),
+                (Quantity::RxPackets, 10.0),
+                (Quantity::TxPackets, 0.5),
+                (Quantity::RxErrors, 3.0),
+                (Quantity::TxErrors, 0.0),
+                (Quantity::RxDropped, 7.0),
+            ]
+        );
+
+        interface.elapsed = Duration::ZERO;
+        assert_eq!(interface_values(&interface)[0], (Quantity::RxBytes, 0.0));
+    }
+
+    #[test]
+    fn test_collect_network() {
+        let mut config = Config::default();
+        config.network.exclude_interfaces = vec!["lo".to_string()];
+        let reader = SystemMetricReader::new(&config);
+        let snapshot = reader.collect(&[Category::Network]);
+
+        assert!(snapshot.failures.is_empty());
+        for metric in &snapshot.metrics {
+            let Metric::Value(_, Category::Interface(name), quantity, value) = metric else {
+                panic!("unexpected metric {:?}", metric);
+            };
+            assert_ne!(name, "lo");
+            assert!(*value >= 0.0, "{} {}", name, quantity.name());
+        }
+        assert!(matches!(
+            reader.get_values(&Category::Interface("nonexistent0".to_string())),
+            Err(MetricError::Unavailable(_))
+        ));
+    }
+
+    // A sysfs tree with two hwmon devices, the second one without labels
+    fn hwmon_fixture(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("srvstat-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files = [
+            ("class/hwmon/hwmon0/name", "coretemp\n"),
+            ("class/hwmon/hwmon0/temp1_label", "Package id 0\n"),
+            ("class/hwmon/hwmon0/temp1_input", "45000\n"),
+            ("class/hwmon/hwmon0/temp1_crit", "100000\n"),
+            ("class/hwmon/hwmon0/temp2_label", "Core 0\n"),
+            ("class/hwmon/hwmon0/temp2_input", "43500\n"),
+            ("class/hwmon/hwmon1/name", "nvme\n"),
+            ("class/hwmon/hwmon1/temp1_input", "38850\n"),
+            ("class/hwmon/hwmon1/temp1_highest", "61000\n"),
+            ("class/hwmon/hwmon1/temp3_crit", "90000\n"),
+            ("class/hwmon/hwmon2/name", "nvme\n"),
+            ("class/hwmon/hwmon2/temp1_label", "Composite\n"),
+            ("class/hwmon/hwmon2/temp1_input", "40000\n"),
+            ("class/hwmon/hwmon3/name", "nvme\n"),
+            ("class/hwmon/hwmon3/temp1_label", "Composite\n"),
+            ("class/hwmon/hwmon3/temp1_input", "42000\n"),
+        ];
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        // The drives are linked to their controller, itself linked to the
+        // PCI device; the first drive has a second controller number
+        let drives = [
+            ("hwmon2", "0000:02:00.0", "nvme1"),
+            ("hwmon3", "0000:03:00.0", "nvme0"),
+        ];
+        for (hwmon, pci, controller) in drives {
+            let pci = root.join("devices/pci0000:00").join(pci);
+            let controller = pci.join("nvme").join(controller);
+            fs::create_dir_all(&controller).unwrap();
+            std::os::unix::fs::symlink(&pci, controller.join("device")).unwrap();
+            let folder = root.join("class/hwmon").join(hwmon);
+            std::os::unix::fs::symlink(&controller, folder.join("device")).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_hwmon_temperatures() {
+        let root = hwmon_fixture("hwmon");
+        let mut temperatures = hwmon_temperatures(&root);
+        fs::remove_dir_all(&root).unwrap();
+        temperatures.sort_by(|a, b| a.label.cmp(&b.label));
+
+        assert_eq!(
+            temperatures,
+            vec![
+                SensorTemperature {
+                    label: "coretemp Core 0".to_string(),
+                    current: 43.5,
+                    max: None,
+                    critical: None,
+                },
+                SensorTemperature {
+                    label: "coretemp Package id 0".to_string(),
+                    current: 45.0,
+                    max: None,
+                    critical: Some(100.0),
+                },
+                SensorTemperature {
+                    label: "nvme Composite 0000:02:00.0".to_string(),
+                    current: 40.0,
+                    max: None,
+                    critical: None,
+                },
+                SensorTemperature {
+                    label: "nvme Composite 0000:03:00.0".to_string(),
+                    current: 42.0,
+                    max: None,
+                    critical: None,
+                },
+                SensorTemperature {
+                    label: "nvme temp1".to_string(),
+                    current: 38.85,
+                    max: Some(61.0),
+                    critical: None,
+                },
+            ]
+        );
+        assert!(hwmon_temperatures(Path::new("/nonexistent")).is_empty());
+    }
+
+    #[test]
+    fn test_collect_temperatures() {
+        let root = hwmon_fixture("temperature");
+        let mut config = Config::default();
+        config.collection.sysfs_root = root.clone();
+        let reader = SystemMetricReader::new(&config);
+        let first = reader.collect(&[Category::Temperature]);
+        fs::write(root.join("class/hwmon/hwmon0/temp2_input"), "41000\n").unwrap();
+        let second = reader.collect(&[Category::Temperature]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(first.failures.is_empty());
+        let core = Category::Sensor("coretemp Core 0".to_string());
+        assert_eq!(
+            first.metrics[..2],
+            [
+                Metric::Value(host().unwrap(), core.clone(), Quantity::Temperature, 43.5),
+                Metric::Value(
+                    host().unwrap(),
+                    core.clone(),
+                    Quantity::TemperatureMax,
+                    43.5
+                ),
+            ]
+        );
+        // The highest temperature seen is kept
+        assert_eq!(
+            second.metrics[..2],
+            [
+                Metric::Value(host().unwrap(), core.clone(), Quantity::Temperature, 41.0),
+                Metric::Value(host().unwrap(), core, Quantity::TemperatureMax, 43.5),
+            ]
+        );
+        assert!(first.metrics.contains(&Metric::Value(
+            host().unwrap(),
+            Category::Sensor("coretemp Package id 0".to_string()),
+            Quantity::TemperatureCritical,
+            100.0
+        )));
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        assert_eq!(cpu_percent(42.4), Percentage(42));
+        assert_eq!(cpu_percent(42.6), Percentage(43));
+        assert_eq!(cpu_percent(100.3), Percentage(100));
+        assert_eq!(cpu_percent(-0.1), Percentage(0));
+    }
+
+    #[test]
+    fn test_expand_cpu_per_core() {
+        let reader = SystemMetricReader::new(&Config::default());
+        let categories = reader.expand(&Category::Cpu);
+        assert_eq!(categories[0], Category::Cpu);
+        assert_eq!(categories.len(), reader.sys().cpus().len() + 1);
+        assert_eq!(categories.get(1), Some(&Category::Core(0)));
+
+        let mut config = Config::default();
+        config.cpu.per_core = false;
+        let reader = SystemMetricReader::new(&config);
+        assert_eq!(reader.expand(&Category::Cpu), vec![Category::Cpu]);
+    }
+
+    #[test]
+    fn test_cpu_readings_respect_minimum_interval() {
+        let reader = SystemMetricReader::new(&Config::default());
+        let start = Instant::now();
+        reader.get_percent(&Category::Cpu).unwrap();
+        reader.get_percent(&Category::Cpu).unwrap();
+        assert!(start.elapsed() >= MINIMUM_CPU_UPDATE_INTERVAL * 2);
     }
 }
@@ -1,88 +1,720 @@
-use crate::domain::ha::models::HomeAssistantDiscoveryConfig;
+use crate::config::MqttConfig;
+use crate::domain::alerts::models::AlertTransition;
+use crate::domain::ha::models::{
+    alert_topic, availability_topic, HomeAssistantDevice, HomeAssistantDiscoveryConfig,
+    PAYLOAD_OFFLINE, PAYLOAD_ONLINE,
+};
+use crate::domain::metrics::change_filter::ChangeFilter;
 use crate::domain::metrics::models::{Metric, Percentage};
-use crate::domain::ports::MetricWriter;
+use crate::domain::ports::{AlertNotifier, MetricError, MetricWriter};
+use crate::outbound::message_queue::{MessageQueue, QueuedMessage};
 use paho_mqtt as mqtt;
-use paho_mqtt::{Client, QOS_0};
-use std::process;
-use std::time::Duration;
+use paho_mqtt::{AsyncClient, QOS_0, QOS_1};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+use sysinfo::System;
+use thiserror::Error;
 
 pub struct DummyMetricWriter;
 
 impl MetricWriter for DummyMetricWriter {
-    fn write(&self, metric: Metric) {
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
         println!("{:?}", metric);
+        Ok(())
     }
 }
 
+/// Prints the alert level changes on the standard output.
+pub struct ConsoleAlertNotifier;
+
+impl AlertNotifier for ConsoleAlertNotifier {
+    fn notify(&self, transition: &AlertTransition) -> Result<(), MetricError> {
+        println!("Alert {}", transition);
+        Ok(())
+    }
+}
+
+/// Errors raised while setting up the MQTT connection.
+#[derive(Debug, Error)]
+pub enum MqttError {
+    #[error("No MQTT broker URL configured")]
+    MissingBroker,
+    #[error("Unable to read password file {path}: {source}")]
+    PasswordFile { path: PathBuf, source: io::Error },
+    #[error("Unable to load message queue {path}: {source}")]
+    QueueFile { path: PathBuf, source: io::Error },
+    #[error("Invalid TLS options: {0}")]
+    Tls(#[source] mqtt::Error),
+    #[error("Error creating the client: {0}")]
+    Client(#[source] mqtt::Error),
+}
+
 #[derive(Clone)]
 pub struct MqttMetricWriter {
-    client: Client,
+    client: AsyncClient,
+    discovery_prefix: String,
+    availability_topic: String,
+    device: HomeAssistantDevice,
+    // Time after which Home Assistant marks a sensor without update unavailable
+    expire_after: Duration,
+    // State messages waiting for the broker to come back
+    queue: Arc<Mutex<MessageQueue>>,
+    // Skips the states that did not change enough since last published
+    filter: Arc<Mutex<ChangeFilter>>,
+    // Discovery topics published on the current connection
+    discovered: Arc<Mutex<HashSet<String>>>,
+    // Discovery configs of the alert entities, by topic: unlike the sensors,
+    // they are not published again by the next write
+    alert_configs: Arc<Mutex<BTreeMap<String, QueuedMessage>>>,
 }
 
 impl MqttMetricWriter {
-    pub fn new(broker: String) -> Self {
+    pub fn new(config: &MqttConfig, expire_after: Duration) -> Result<Self, MqttError> {
+        let broker = config.broker_url.clone().ok_or(MqttError::MissingBroker)?;
+
         // Create a client & define connect options
-        let client = Client::new(broker).unwrap_or_else(|err| {
-            println!("Error creating the client: {:?}", err);
-            process::exit(1);
+        let mut create_opts = mqtt::CreateOptionsBuilder::new().server_uri(&broker);
+        if let Some(client_id) = &config.client_id {
+            create_opts = create_opts.client_id(client_id);
+        }
+        let client = AsyncClient::new(create_opts.finalize()).map_err(MqttError::Client)?;
+        let queue = match &config.queue_file {
+            Some(path) => {
+                MessageQueue::with_file(config.queue_size, path.clone()).map_err(|source| {
+                    MqttError::QueueFile {
+                        path: path.clone(),
+                        source,
+                    }
+                })?
+            }
+            None => MessageQueue::new(config.queue_size),
+        };
+        let queue = Arc::new(Mutex::new(queue));
+
+        // Mark the host online on every (re)connection; the broker publishes
+        // the Last Will (offline) if the connection is lost. The broker may
+        // have lost the discovery configs and states meanwhile, so they are
+        // all published again: the alert configs right away, followed by the
+        // queued messages, and the sensor configs with their next state.
+        let host = System::host_name().unwrap_or_else(|| "localhost".to_string());
+        let availability_topic = availability_topic(&host);
+        let online_topic = availability_topic.clone();
+        let filter = Arc::new(Mutex::new(ChangeFilter::new(
+            config.deadband.clone(),
+            config.heartbeat,
+        )));
+        let discovered = Arc::new(Mutex::new(HashSet::new()));
+        let (reset_filter, reset_discovered) = (Arc::clone(&filter), Arc::clone(&discovered));
+        let alert_configs = Arc::new(Mutex::new(BTreeMap::new()));
+        let (pending, alerts) = (Arc::clone(&queue), Arc::clone(&alert_configs));
+        client.set_connected_callback(move |client| {
+            client.publish(mqtt::Message::new_retained(
+                &online_topic,
+                PAYLOAD_ONLINE,
+                QOS_1,
+            ));
+            reset_filter
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .reset();
+            reset_discovered
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+            // Publishes are completed on this thread, so waiting for them here
+            // would never return
+            let (client, pending, alerts) =
+                (client.clone(), Arc::clone(&pending), Arc::clone(&alerts));
+            let discovered = Arc::clone(&reset_discovered);
+            thread::spawn(move || {
+                publish_configs(&client, &alerts, &discovered);
+                flush_queue(&client, &pending);
+            });
         });
 
-        let conn_opts = mqtt::ConnectOptionsBuilder::new()
-            .keep_alive_interval(Duration::from_secs(20))
-            .clean_session(true)
-            .finalize();
+        let conn_opts = connect_options(config, &availability_topic)?;
 
-        // Connect and wait for it to complete or fail
-        if let Err(e) = client.connect(conn_opts) {
-            println!("Unable to connect:\n\t{:?}", e);
-            process::exit(1);
+        // Connect and wait for it to complete or fail. An unreachable broker
+        // does not prevent starting: the states are queued meanwhile
+        if let Err(e) = client.connect(conn_opts.clone()).wait() {
+            eprintln!("Unable to connect to {}: {}", broker, e);
+            connect_in_background(client.clone(), conn_opts, config, broker);
         }
-        MqttMetricWriter { client }
+        Ok(MqttMetricWriter {
+            client,
+            discovery_prefix: config.discovery_prefix.clone(),
+            availability_topic,
+            device: detect_device(&host),
+            expire_after,
+            queue,
+            filter,
+            discovered,
+            alert_configs,
+        })
     }
 
-    fn publish_autodiscovery_config(&self, config: &HomeAssistantDiscoveryConfig) {
-        let discovery_payload = serde_json::to_string(&config.clone()).unwrap();
+    // Configs are retained and sent once per connection. A failed discovery
+    // publish is only logged: a sensor's is retried on its next write, the
+    // state being queued until the broker comes back, and an alert's on the
+    // next connection
+    fn publish_autodiscovery_config(
+        &self,
+        config: &HomeAssistantDiscoveryConfig,
+    ) -> Result<(), serde_json::Error> {
         let discovery_topic = config.clone().get_config_topic();
+        let discovery_payload = serde_json::to_string(config)?;
+        // The lock is released before waiting: the connected callback takes
+        // it on the thread that completes the publish
+        if !self
+            .discovered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(discovery_topic.clone())
+        {
+            return Ok(());
+        }
         println!("config topic = {}", discovery_topic);
         println!("config payload = {}", discovery_payload);
-        let msg = mqtt::Message::new(discovery_topic, discovery_payload, QOS_0);
-        let tok = self.client.publish(msg);
-
-        if let Err(e) = tok {
+        let msg = mqtt::Message::new_retained(discovery_topic.as_str(), discovery_payload, QOS_1);
+        if let Err(e) = self.client.publish(msg).wait() {
             println!("Error sending message: {:?}", e);
+            self.discovered
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&discovery_topic);
         }
+        Ok(())
     }
 
-    fn publish_metric_value(self, config: HomeAssistantDiscoveryConfig, val: String) {
-        let state_topic = config.get_state_topic();
-        let payload = serde_json::json!({
-            "value": val
-        });
-        let payload_str = serde_json::to_string(&payload).unwrap();
+    fn publish_metric_value(
+        &self,
+        state_topic: String,
+        payload: Value,
+    ) -> Result<(), serde_json::Error> {
+        let payload_str = serde_json::to_string(&payload)?;
         println!("state topic = {}", &state_topic);
         println!("config payload = {}", &payload_str);
-        let msg = mqtt::Message::new(state_topic, payload_str, QOS_0);
-        let tok = self.client.publish(msg);
+        self.publish_or_queue(QueuedMessage {
+            topic: state_topic,
+            payload: payload_str,
+            retained: false,
+        });
+        Ok(())
+    }
 
-        if let Err(e) = tok {
-            println!("Error sending message: {:?}", e);
+    // Send the queued messages first so the broker receives states in order,
+    // and keep the message for later if the broker is unreachable
+    fn publish_or_queue(&self, message: QueuedMessage) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if self.client.is_connected() {
+            send_queued(&self.client, &mut queue);
+            if queue.is_empty() {
+                match publish(&self.client, &message) {
+                    Ok(()) => return,
+                    Err(e) => eprintln!("Error sending message: {:?}", e),
+                }
+            }
         }
+        queue.push(message);
+        eprintln!("Broker unavailable, {} message(s) queued", queue.len());
+    }
+}
+
+fn publish(client: &AsyncClient, message: &QueuedMessage) -> Result<(), mqtt::Error> {
+    let msg = if message.retained {
+        mqtt::Message::new_retained(&message.topic, message.payload.as_bytes(), QOS_1)
+    } else {
+        mqtt::Message::new(&message.topic, message.payload.as_bytes(), QOS_0)
+    };
+    client.publish(msg).wait()
+}
+
+// Send the remembered discovery configs that this connection has not seen
+fn publish_configs(
+    client: &AsyncClient,
+    configs: &Mutex<BTreeMap<String, QueuedMessage>>,
+    discovered: &Mutex<HashSet<String>>,
+) {
+    let configs: Vec<QueuedMessage> = configs
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .cloned()
+        .collect();
+    for config in configs {
+        if discovered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&config.topic)
+        {
+            continue;
+        }
+        match publish(client, &config) {
+            Ok(()) => {
+                discovered
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(config.topic);
+            }
+            Err(e) => println!("Error sending message: {:?}", e),
+        }
+    }
+}
+
+// Send the messages queued while the broker was unreachable, in order
+fn flush_queue(client: &AsyncClient, queue: &Mutex<MessageQueue>) {
+    send_queued(client, &mut queue.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+fn send_queued(client: &AsyncClient, queue: &mut MessageQueue) {
+    let sent = queue.flush(|queued| publish(client, queued).is_ok());
+    if sent > 0 {
+        println!("Sent {} queued message(s)", sent);
     }
 }
 
 impl MetricWriter for MqttMetricWriter {
-    fn write(&self, metric: Metric) {
-        let config = HomeAssistantDiscoveryConfig::from(&metric);
-        // Publish Home Assistant autodiscovery config
-        self.publish_autodiscovery_config(&config);
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
+        let changed = self
+            .filter
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .should_publish(&metric, Instant::now());
+        if !changed {
+            return Ok(());
+        }
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric)?
+            .with_discovery_prefix(&self.discovery_prefix)
+            .with_expire_after(self.expire_after)
+            .with_device(self.device.clone());
+        let payload = state_payload(&config, &metric);
+        let to_error = |e: serde_json::Error| MetricError::Write {
+            metric: metric.to_string(),
+            reason: e.to_string(),
+        };
+        let topic = match metric {
+            // The top processes are attributes of the CPU or memory usage
+            // sensor, which is discovered along with its own state
+            Metric::Top(..) => config.get_json_attributes_topic().unwrap_or_default(),
+            _ => {
+                // Publish Home Assistant autodiscovery config
+                self.publish_autodiscovery_config(&config)
+                    .map_err(to_error)?;
+                config.get_state_topic()
+            }
+        };
         // Publish actual metric value
-        let val: String = match metric {
-            Metric::Percent(_, _, percent) => {
-                let Percentage(val) = percent;
-                val.to_string()
+        self.publish_metric_value(topic, payload).map_err(to_error)
+    }
+
+    fn close(&self) {
+        // A clean disconnect does not trigger the Last Will
+        let offline = mqtt::Message::new_retained(&self.availability_topic, PAYLOAD_OFFLINE, QOS_1);
+        if let Err(e) = self.client.publish(offline).wait() {
+            println!("Error sending message: {:?}", e);
+        }
+        if let Err(e) = self.client.disconnect(None).wait() {
+            println!("Error disconnecting: {:?}", e);
+        }
+    }
+}
+
+// The level is retained so that subscribers get the current one right away,
+// and queued like the states while the broker is unreachable, since the engine
+// only reports each transition once
+impl AlertNotifier for MqttMetricWriter {
+    fn notify(&self, transition: &AlertTransition) -> Result<(), MetricError> {
+        // Home Assistant problem entity of the rule, on at WARN and CRIT
+        let config = HomeAssistantDiscoveryConfig::from(transition)
+            .with_discovery_prefix(&self.discovery_prefix)
+            .with_device(self.device.clone());
+        match serde_json::to_string(&config) {
+            Ok(payload) => {
+                let topic = config.clone().get_config_topic();
+                let message = QueuedMessage {
+                    topic: topic.clone(),
+                    payload,
+                    retained: true,
+                };
+                self.alert_configs
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(topic, message);
+                // Cannot fail, the config being serialized above
+                let _ = self.publish_autodiscovery_config(&config);
             }
-            Metric::Used(_, _, _, _) => "".to_string(),
+            Err(e) => println!("Error serializing discovery config: {:?}", e),
+        }
+        let topic = alert_topic(&transition.host, &transition.rule, &transition.category);
+        self.publish_or_queue(QueuedMessage {
+            topic,
+            payload: alert_payload(transition).to_string(),
+            retained: true,
+        });
+        Ok(())
+    }
+}
+
+fn alert_payload(transition: &AlertTransition) -> Value {
+    json!({
+        "rule": transition.rule,
+        "category": transition.category.to_string(),
+        "level": transition.level,
+        "previous": transition.previous,
+        "value": transition.value,
+    })
+}
+
+/// Builds the state payload of a metric. Used/total byte counts are converted
+/// to the unit advertised in the discovery config, the total being exposed as
+/// a JSON attribute, like the statistics of a window besides its mean. The
+/// top processes are a list of attributes, without a state.
+fn state_payload(config: &HomeAssistantDiscoveryConfig, metric: &Metric) -> Value {
+    match metric {
+        Metric::Percent(_, _, Percentage(val)) => json!({
+            "value": val.to_string()
+        }),
+        Metric::Used(_, _, used, total) => json!({
+            "value": config.scale_bytes(*used),
+            "total": config.scale_bytes(*total)
+        }),
+        Metric::Window(_, _, window) => json!({
+            "value": window.mean.0.to_string(),
+            "min": window.min.0,
+            "max": window.max.0,
+            "p95": window.p95.0,
+            "samples": window.samples
+        }),
+        Metric::Value(_, _, _, value) => json!({
+            "value": value
+        }),
+        Metric::Top(_, _, processes) => json!({
+            "top": processes
+                .iter()
+                .map(|process| json!({
+                    "name": process.name,
+                    "pid": process.pid,
+                    "cpu": (process.cpu * 10.0).round() / 10.0,
+                    "memory": process.memory,
+                }))
+                .collect::<Vec<_>>()
+        }),
+    }
+}
+
+/// Files holding the machine-id, in order of preference.
+const MACHINE_ID_FILES: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Describes the host as a Home Assistant device, identified by its machine-id
+/// (or its host name when there is none).
+fn detect_device(host: &str) -> HomeAssistantDevice {
+    let id = MACHINE_ID_FILES
+        .iter()
+        .find_map(|path| machine_id(Path::new(path)))
+        .unwrap_or_else(|| host.to_lowercase());
+    let os = System::long_os_version().unwrap_or_else(|| "Unknown OS".to_string());
+    let model = match System::kernel_version() {
+        Some(kernel) => format!("{} (kernel {})", os, kernel),
+        None => os,
+    };
+    HomeAssistantDevice::new(&id, host, model)
+}
+
+fn machine_id(path: &Path) -> Option<String> {
+    let id = fs::read_to_string(path).ok()?;
+    let id = id.trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+// Retry the first connection until the broker answers, waiting longer after
+// each failure; paho then reconnects on its own whenever the connection is lost
+fn connect_in_background(
+    client: AsyncClient,
+    conn_opts: mqtt::ConnectOptions,
+    config: &MqttConfig,
+    broker: String,
+) {
+    let (mut delay, max_delay) = (config.reconnect_min_delay, config.reconnect_max_delay);
+    thread::spawn(move || loop {
+        thread::sleep(delay);
+        match client.connect(conn_opts.clone()).wait() {
+            Ok(_) => {
+                println!("Connected to {}", broker);
+                break;
+            }
+            Err(e) => eprintln!("Unable to connect to {}: {}", broker, e),
+        }
+        delay = (delay * 2).min(max_delay);
+    });
+}
+
+/// Builds the connect options (credentials, TLS, keep-alive, Last Will) from the config.
+fn connect_options(
+    config: &MqttConfig,
+    availability_topic: &str,
+) -> Result<mqtt::ConnectOptions, MqttError> {
+    let mut builder = mqtt::ConnectOptionsBuilder::new();
+    builder
+        .keep_alive_interval(config.keep_alive)
+        .clean_session(true)
+        .will_message(mqtt::Message::new_retained(
+            availability_topic,
+            PAYLOAD_OFFLINE,
+            QOS_1,
+        ))
+        .automatic_reconnect(config.reconnect_min_delay, config.reconnect_max_delay);
+
+    if let Some(username) = &config.username {
+        builder.user_name(username);
+    }
+    if let Some(password) = password(config)? {
+        builder.password(password);
+    }
+
+    // Secure schemes need SSL options even without any file, to trust the
+    // system CAs
+    if uses_tls(config) || config.ca_file.is_some() || config.client_cert.is_some() {
+        let mut ssl = mqtt::SslOptionsBuilder::new();
+        if let Some(ca_file) = &config.ca_file {
+            ssl.trust_store(ca_file).map_err(MqttError::Tls)?;
+        }
+        if let Some(client_cert) = &config.client_cert {
+            ssl.key_store(client_cert).map_err(MqttError::Tls)?;
+        }
+        if let Some(client_key) = &config.client_key {
+            ssl.private_key(client_key).map_err(MqttError::Tls)?;
+        }
+        builder.ssl_options(ssl.finalize());
+    }
+    Ok(builder.finalize())
+}
+
+fn uses_tls(config: &MqttConfig) -> bool {
+    config
+        .broker_url
+        .as_deref()
+        .and_then(|url| url.split_once("://"))
+        .is_some_and(|(scheme, _)| matches!(scheme, "ssl" | "mqtts" | "wss"))
+}
+
+/// Returns the configured password, reading it from `password_file` if needed.
+fn password(config: &MqttConfig) -> Result<Option<String>, MqttError> {
+    match &config.password_file {
+        Some(path) => fs::read_to_string(path)
+            .map(|content| Some(content.trim_end_matches(['\r', '\n']).to_string()))
+            .map_err(|source| MqttError::PasswordFile {
+                path: path.clone(),
+                source,
+            }),
+        None => Ok(config.password.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::alerts::models::AlertLevel;
+    use crate::domain::metrics::models::{Category, ProcessUsage, Window};
+    use std::env;
+
+    #[test]
+    fn test_password_from_config() {
+        let config = MqttConfig {
+            password: Some("secret".to_string()),
+            ..MqttConfig::default()
+        };
+        assert_eq!(password(&config).unwrap().as_deref(), Some("secret"));
+        assert_eq!(password(&MqttConfig::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_password_from_file() {
+        let path = env::temp_dir().join(format!("srvstat-password-{}", std::process::id()));
+        fs::write(&path, "s3cr3t\n").unwrap();
+        let config = MqttConfig {
+            password_file: Some(path.clone()),
+            ..MqttConfig::default()
+        };
+        let result = password(&config);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap().as_deref(), Some("s3cr3t"));
+    }
+
+    #[test]
+    fn test_missing_password_file() {
+        let config = MqttConfig {
+            password_file: Some(PathBuf::from("/nonexistent/srvstat-password")),
+            ..MqttConfig::default()
+        };
+        assert!(matches!(
+            connect_options(&config, "srvstat/test/availability"),
+            Err(MqttError::PasswordFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ssl_without_files() {
+        let options = |broker_url: &str| {
+            let config = MqttConfig {
+                broker_url: Some(broker_url.to_string()),
+                ..MqttConfig::default()
+            };
+            connect_options(&config, "srvstat/test/availability").unwrap()
+        };
+        assert!(options("ssl://broker:8883").has_ssl_options());
+        assert!(options("mqtts://broker:8883").has_ssl_options());
+        assert!(options("wss://broker:443").has_ssl_options());
+        assert!(!options("tcp://broker:1883").has_ssl_options());
+    }
+
+    #[test]
+    fn test_state_payload_percent() {
+        let metric = Metric::Percent(
+            "test-host".to_string(),
+            Category::Cpu,
+            Percentage::new(42).unwrap(),
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(state_payload(&config, &metric), json!({"value": "42"}));
+    }
+
+    #[test]
+    fn test_state_payload_window() {
+        let metric = Metric::Window(
+            "test-host".to_string(),
+            Category::Cpu,
+            Window {
+                min: Percentage(5),
+                max: Percentage(95),
+                mean: Percentage(30),
+                p95: Percentage(90),
+                samples: 12,
+            },
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(
+            state_payload(&config, &metric),
+            json!({"value": "30", "min": 5, "max": 95, "p95": 90, "samples": 12})
+        );
+    }
+
+    #[test]
+    fn test_state_payload_top() {
+        let metric = Metric::Top(
+            "test-host".to_string(),
+            Category::Cpu,
+            vec![ProcessUsage {
+                name: "cargo".to_string(),
+                pid: 99,
+                cpu: 180.04,
+                memory: 2048,
+            }],
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(
+            state_payload(&config, &metric),
+            json!({"top": [{"name": "cargo", "pid": 99, "cpu": 180.0, "memory": 2048}]})
+        );
+    }
+
+    #[test]
+    fn test_state_payload_used() {
+        let metric = Metric::Used(
+            "test-host".to_string(),
+            Category::Disk,
+            250_000_000_000,
+            1_000_000_000_000,
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(
+            state_payload(&config, &metric),
+            json!({"value": 250.0, "total": 1000.0})
+        );
+
+        let metric = Metric::Used(
+            "test-host".to_string(),
+            Category::Memory,
+            2_048_000_000,
+            8_192_000_000,
+        );
+        let config = HomeAssistantDiscoveryConfig::try_from(&metric).unwrap();
+        assert_eq!(
+            state_payload(&config, &metric),
+            json!({"value": 2048.0, "total": 8192.0})
+        );
+    }
+
+    #[test]
+    fn test_machine_id() {
+        let path = env::temp_dir().join(format!("srvstat-machine-id-{}", std::process::id()));
+        fs::write(&path, "0123456789abcdef\n").unwrap();
+        let id = machine_id(&path);
+        fs::write(&path, "\n").unwrap();
+        let empty = machine_id(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(id.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(empty, None);
+        assert_eq!(machine_id(Path::new("/nonexistent/machine-id")), None);
+    }
+
+    #[test]
+    fn test_new_without_broker() {
+        assert!(matches!(
+            MqttMetricWriter::new(&MqttConfig::default(), Duration::from_secs(300)),
+            Err(MqttError::MissingBroker)
+        ));
+    }
+
+    #[test]
+    fn test_new_connection_refused_queues() {
+        let path = env::temp_dir().join(format!("srvstat-refused-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = MqttConfig {
+            broker_url: Some("tcp://127.0.0.1:1".to_string()),
+            client_id: Some("srvstat-test".to_string()),
+            queue_file: Some(path.clone()),
+            ..MqttConfig::default()
+        };
+        let writer = MqttMetricWriter::new(&config, Duration::from_secs(300)).unwrap();
+        let metric = Metric::Percent(
+            "test-host".to_string(),
+            Category::Cpu,
+            Percentage::new(42).unwrap(),
+        );
+        writer.write(metric).unwrap();
+        let queued = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(queued
+            .unwrap()
+            .contains("\"topic\":\"homeassistant/sensor/test-hostcpuusepercent/state\""));
+    }
+
+    #[test]
+    fn test_alert_payload() {
+        let transition = AlertTransition {
+            rule: "disk_full".to_string(),
+            host: "tux".to_string(),
+            category: Category::Disk,
+            previous: Some(AlertLevel::Ok),
+            level: AlertLevel::Crit,
+            value: 93,
+            timestamp: std::time::SystemTime::UNIX_EPOCH,
         };
-        self.clone().publish_metric_value(config, val);
+        assert_eq!(
+            alert_payload(&transition),
+            json!({
+                "rule": "disk_full",
+                "category": "Disk",
+                "level": "CRIT",
+                "previous": "OK",
+                "value": 93
+            })
+        );
     }
 }
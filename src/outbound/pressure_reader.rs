@@ -0,0 +1,173 @@
+use crate::domain::metrics::models::Quantity;
+use std::fs;
+use std::path::Path;
+
+/// Resources with pressure stall information, in reporting order.
+const RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+/// Share of time tasks stalled on a resource, averaged over 10, 60 and 300
+/// seconds, in percent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Averages {
+    avg10: f64,
+    avg60: f64,
+    avg300: f64,
+}
+
+/// Pressure stall information of a resource: when some tasks stalled, and
+/// when all of them did (not reported for the CPU by older kernels).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourcePressure {
+    /// `cpu`, `memory` or `io`.
+    pub resource: String,
+    some: Option<Averages>,
+    full: Option<Averages>,
+}
+
+impl ResourcePressure {
+    pub fn values(&self) -> Vec<(Quantity, f64)> {
+        let mut values = Vec::new();
+        if let Some(some) = self.some {
+            values.push((Quantity::SomeAvg10, some.avg10));
+            values.push((Quantity::SomeAvg60, some.avg60));
+            values.push((Quantity::SomeAvg300, some.avg300));
+        }
+        if let Some(full) = self.full {
+            values.push((Quantity::FullAvg10, full.avg10));
+            values.push((Quantity::FullAvg60, full.avg60));
+            values.push((Quantity::FullAvg300, full.avg300));
+        }
+        values
+    }
+}
+
+/// Whether the kernel reports pressure stall information, which needs
+/// CONFIG_PSI (and `psi=1` on some distributions).
+pub fn is_available(procfs_root: &Path) -> bool {
+    procfs_root.join("pressure").is_dir()
+}
+
+/// Pressure of every resource the kernel reports, none without PSI.
+pub fn read_pressure(procfs_root: &Path) -> Vec<ResourcePressure> {
+    RESOURCES
+        .iter()
+        .filter_map(|resource| {
+            let content = fs::read_to_string(procfs_root.join("pressure").join(resource)).ok()?;
+            Some(parse_pressure(resource, &content))
+        })
+        .collect()
+}
+
+// Parse lines such as `some avg10=0.12 avg60=0.05 avg300=0.01 total=123456`
+fn parse_pressure(resource: &str, content: &str) -> ResourcePressure {
+    let mut pressure = ResourcePressure {
+        resource: resource.to_string(),
+        some: None,
+        full: None,
+    };
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next();
+        let mut averages = [None; 3];
+        for field in fields {
+            let Some((key, value)) = field.split_once('=') else {
+                continue;
+            };
+            let index = match key {
+                "avg10" => 0,
+                "avg60" => 1,
+                "avg300" => 2,
+                _ => continue,
+            };
+            averages[index] = value.parse::<f64>().ok();
+        }
+        let [Some(avg10), Some(avg60), Some(avg300)] = averages else {
+            continue;
+        };
+        let averages = Some(Averages {
+            avg10,
+            avg60,
+            avg300,
+        });
+        match kind {
+            Some("some") => pressure.some = averages,
+            Some("full") => pressure.full = averages,
+            _ => {}
+        }
+    }
+    pressure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    // A procfs root whose CPU pressure has no `full` line, as before Linux 5.13
+    fn procfs_fixture(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("srvstat-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let pressure = root.join("pressure");
+        fs::create_dir_all(&pressure).unwrap();
+        fs::write(
+            pressure.join("cpu"),
+            "some avg10=2.04 avg60=0.75 avg300=0.40 total=157656722\n",
+        )
+        .unwrap();
+        fs::write(
+            pressure.join("memory"),
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\nfull avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+        )
+        .unwrap();
+        fs::write(
+            pressure.join("io"),
+            "some avg10=12.50 avg60=3.10 avg300=1.00 total=987654\nfull avg10=10.00 avg60=2.50 avg300=0.80 total=876543\n",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn test_read_pressure() {
+        let root = procfs_fixture("pressure");
+        assert!(is_available(&root));
+        let pressures = read_pressure(&root);
+        fs::remove_dir_all(&root).unwrap();
+
+        let resources: Vec<&str> = pressures.iter().map(|p| p.resource.as_str()).collect();
+        assert_eq!(resources, vec!["cpu", "memory", "io"]);
+        assert_eq!(
+            pressures[0].values(),
+            vec![
+                (Quantity::SomeAvg10, 2.04),
+                (Quantity::SomeAvg60, 0.75),
+                (Quantity::SomeAvg300, 0.4),
+            ]
+        );
+        assert_eq!(
+            pressures[2].values(),
+            vec![
+                (Quantity::SomeAvg10, 12.5),
+                (Quantity::SomeAvg60, 3.1),
+                (Quantity::SomeAvg300, 1.0),
+                (Quantity::FullAvg10, 10.0),
+                (Quantity::FullAvg60, 2.5),
+                (Quantity::FullAvg300, 0.8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_without_psi() {
+        let root = env::temp_dir().join(format!("srvstat-no-psi-{}", std::process::id()));
+        assert!(!is_available(&root));
+        assert!(read_pressure(&root).is_empty());
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let pressure = parse_pressure("cpu", "some avg10=x avg60=0.1 avg300=0.1\nfull\n");
+        assert!(pressure.values().is_empty());
+    }
+}
@@ -0,0 +1,554 @@
+use crate::domain::metrics::models::{Category, Metric, Percentage, Quantity};
+use crate::domain::ports::{MetricError, MetricWriter};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Time a scraper has to send its request and read the response.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A metric family exposed on `/metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Family {
+    UsagePercent,
+    UsedBytes,
+    TotalBytes,
+    WindowPercent,
+    Value(Quantity),
+    TopProcessCpu,
+    TopProcessMemory,
+}
+
+impl Family {
+    fn name(self) -> String {
+        match self {
+            Family::UsagePercent => "srvstat_usage_percent".to_string(),
+            Family::UsedBytes => "srvstat_used_bytes".to_string(),
+            Family::TotalBytes => "srvstat_total_bytes".to_string(),
+            Family::WindowPercent => "srvstat_usage_window_percent".to_string(),
+            // Suffixed with the base unit, e.g. srvstat_uptime_seconds or
+            // srvstat_rx_bytes_per_second, or _total for counters
+            Family::Value(quantity) => match quantity.unit() {
+                Some("s") => format!("srvstat_{}_seconds", quantity.name()),
+                Some("°C") => format!("srvstat_{}_celsius", quantity.name()),
+                Some("%") => format!("srvstat_{}_percent", quantity.name()),
+                Some("B") => format!("srvstat_{}_bytes", quantity.name()),
+                Some(unit) if unit.ends_with("/s") => {
+                    format!("srvstat_{}_per_second", quantity.name())
+                }
+                _ if quantity.is_counter() => format!("srvstat_{}_total", quantity.name()),
+                _ => format!("srvstat_{}", quantity.name()),
+            },
+            Family::TopProcessCpu => "srvstat_top_process_cpu_percent".to_string(),
+            Family::TopProcessMemory => "srvstat_top_process_memory_bytes".to_string(),
+        }
+    }
+
+    fn kind(self) -> &'static str {
+        match self {
+            Family::Value(quantity) if quantity.is_counter() => "counter",
+            _ => "gauge",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Family::UsagePercent => "Resource usage, in percent.",
+            Family::UsedBytes => "Used amount of the resource, in bytes.",
+            Family::TotalBytes => "Total amount of the resource, in bytes.",
+            Family::WindowPercent => {
+                "Resource usage over the last publish window, in percent, by statistic."
+            }
+            Family::Value(quantity) => quantity.description(),
+            Family::TopProcessCpu => {
+                "CPU usage of the processes using the most CPU, in percent of one core, by rank."
+            }
+            Family::TopProcessMemory => {
+                "Resident memory of the processes using the most memory, in bytes, by rank."
+            }
+        }
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+/// Latest value of every series, keyed by family and labels, with when it
+/// was written.
+type Series = BTreeMap<(Family, Labels), (f64, Instant)>;
+
+/// Serves the latest value of every written metric on an HTTP `/metrics`
+/// endpoint, in the Prometheus text exposition format.
+///
+/// Series that are no longer written, such as those of an unmounted
+/// filesystem or a removed cgroup, are dropped after `expire_after`.
+#[derive(Clone)]
+pub struct PrometheusMetricWriter {
+    series: Arc<Mutex<Series>>,
+    local_addr: SocketAddr,
+    expire_after: Duration,
+}
+
+impl PrometheusMetricWriter {
+    /// Listens on `listen` (e.g. `0.0.0.0:9184`) and serves every scrape from
+    /// its own thread, so that a stalled client does not hold up the others.
+    pub fn new(listen: &str, expire_after: Duration) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        let writer = PrometheusMetricWriter {
+            series: Arc::new(Mutex::new(Series::new())),
+            local_addr: listener.local_addr()?,
+            expire_after,
+        };
+        let served = writer.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let served = served.clone();
+                let result = stream.map(|stream| {
+                    thread::spawn(move || {
+                        if let Err(e) = served.handle(stream) {
+                            eprintln!("Error serving metrics: {}", e);
+                        }
+                    })
+                });
+                if let Err(e) = result {
+                    eprintln!("Error serving metrics: {}", e);
+                }
+            }
+        });
+        println!(
+            "Serving Prometheus metrics on http://{}/metrics",
+            writer.local_addr
+        );
+        Ok(writer)
+    }
+
+    /// Address the exporter listens on, useful when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Answer a single request, `/metrics` being the only known path
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render(Instant::now())),
+            (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+
+    // Render the series written within `expire_after` of `now`, forgetting
+    // the older ones
+    fn render(&self, now: Instant) -> String {
+        let mut series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        series
+            .retain(|_, (_, written)| now.saturating_duration_since(*written) < self.expire_after);
+        render(&series)
+    }
+}
+
+impl MetricWriter for PrometheusMetricWriter {
+    fn write(&self, metric: Metric) -> Result<(), MetricError> {
+        let mut series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let mut insert = |key: (Family, Labels), value: f64| {
+            series.insert(key, (value, now));
+        };
+        match metric {
+            Metric::Percent(host, category, Percentage(value)) => {
+                insert(
+                    (Family::UsagePercent, labels(&host, &category)),
+                    value.into(),
+                );
+            }
+            Metric::Used(host, category, used, total) => {
+                let labels = labels(&host, &category);
+                insert((Family::UsedBytes, labels.clone()), used as f64);
+                insert((Family::TotalBytes, labels), total as f64);
+            }
+            Metric::Window(host, category, window) => {
+                let stats = [
+                    ("min", window.min),
+                    ("max", window.max),
+                    ("mean", window.mean),
+                    ("p95", window.p95),
+                ];
+                for (stat, Percentage(value)) in stats {
+                    let mut labels = labels(&host, &category);
+                    labels.push(("stat", stat.to_string()));
+                    insert((Family::WindowPercent, labels), value.into());
+                }
+            }
+            Metric::Value(host, category, quantity, value) => {
+                insert((Family::Value(quantity), labels(&host, &category)), value);
+            }
+            Metric::Top(host, category, processes) => {
+                let family = match category {
+                    Category::Memory => Family::TopProcessMemory,
+                    _ => Family::TopProcessCpu,
+                };
+                // Processes out of the ranking must not linger
+                series.retain(|(other, labels), _| {
+                    *other != family || labels.first() != Some(&("host", host.clone()))
+                });
+                for (rank, process) in processes.iter().enumerate() {
+                    let mut labels = labels(&host, &Category::Processes);
+                    labels.push(("rank", (rank + 1).to_string()));
+                    labels.push(("process", process.name.clone()));
+                    labels.push(("pid", process.pid.to_string()));
+                    let value = match family {
+                        Family::TopProcessMemory => process.memory as f64,
+                        _ => f64::from(process.cpu),
+                    };
+                    series.insert((family, labels), (value, now));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Identifies the series of a metric: the host, the kind of resource, and the
+/// mount point, core index, name or label of single filesystems, cores,
+/// interfaces, temperature sensors, watched processes, cgroups and stalled
+/// resources.
+fn labels(host: &str, category: &Category) -> Labels {
+    let mut labels = vec![
+        ("host", host.to_string()),
+        ("category", category.kind().to_string()),
+    ];
+    match category {
+        Category::Filesystem(mount) => labels.push(("mount", mount.clone())),
+        Category::Core(index) => labels.push(("core", index.to_string())),
+        Category::Interface(name) => labels.push(("interface", name.clone())),
+        Category::Sensor(label) => labels.push(("sensor", label.clone())),
+        Category::Watched(name) => labels.push(("watch", name.clone())),
+        Category::Cgroup(path) => labels.push(("cgroup", path.clone())),
+        Category::Psi(resource) => labels.push(("resource", resource.clone())),
+        _ => {}
+    }
+    labels
+}
+
+fn render(series: &Series) -> String {
+    let mut output = String::new();
+    let mut current = None;
+    for ((family, labels), (value, _)) in series {
+        if current != Some(*family) {
+            let _ = writeln!(output, "# HELP {} {}", family.name(), family.help());
+            let _ = writeln!(output, "# TYPE {} {}", family.name(), family.kind());
+            current = Some(*family);
+        }
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        let _ = writeln!(
+            output,
+            "{}{{{}}} {}",
+            family.name(),
+            labels.join(","),
+            value
+        );
+    }
+    output
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::metrics::models::{ProcessUsage, Window};
+    use std::io::Read;
+
+    fn writer() -> PrometheusMetricWriter {
+        PrometheusMetricWriter::new("127.0.0.1:0", Duration::from_secs(60)).unwrap()
+    }
+
+    fn get(writer: &PrometheusMetricWriter, path: &str) -> String {
+        let mut stream = TcpStream::connect(writer.local_addr()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_render() {
+        let writer = writer();
+        writer
+            .write(Metric::Percent(
+                "tux".to_string(),
+                Category::Cpu,
+                Percentage(12),
+            ))
+            .unwrap();
+        writer
+            .write(Metric::Used(
+                "tux".to_string(),
+                Category::Filesystem("/home".to_string()),
+                25,
+                100,
+            ))
+            .unwrap();
+
+        assert_eq!(
+            writer.render(Instant::now()),
+            "# HELP srvstat_usage_percent Resource usage, in percent.\n\
+             # TYPE srvstat_usage_percent gauge\n\
+             srvstat_usage_percent{host=\"tux\",category=\"cpu\"} 12\n\
+             # HELP srvstat_used_bytes Used amount of the resource, in bytes.\n\
+             # TYPE srvstat_used_bytes gauge\n\
+             srvstat_used_bytes{host=\"tux\",category=\"filesystem\",mount=\"/home\"} 25\n\
+             # HELP srvstat_total_bytes Total amount of the resource, in bytes.\n\
+             # TYPE srvstat_total_bytes gauge\n\
+             srvstat_total_bytes{host=\"tux\",category=\"filesystem\",mount=\"/home\"} 100\n"
+        );
+    }
+
+    #[test]
+    fn test_latest_value_wins() {
+        let writer = writer();
+        for value in [10, 20] {
+            writer
+                .write(Metric::Percent(
+                    "tux".to_string(),
+                    Category::Core(1),
+                    Percentage(value),
+                ))
+                .unwrap();
+        }
+        let rendered = writer.render(Instant::now());
+        assert!(rendered
+            .contains("srvstat_usage_percent{host=\"tux\",category=\"core\",core=\"1\"} 20\n"));
+        assert!(!rendered.contains("} 10\n"));
+    }
+
+    #[test]
+    fn test_window() {
+        let writer = writer();
+        writer
+            .write(Metric::Window(
+                "tux".to_string(),
+                Category::Cpu,
+                Window {
+                    min: Percentage(5),
+                    max: Percentage(95),
+                    mean: Percentage(30),
+                    p95: Percentage(90),
+                    samples: 12,
+                },
+            ))
+            .unwrap();
+        let rendered = writer.render(Instant::now());
+        assert!(rendered.contains("# TYPE srvstat_usage_window_percent gauge\n"));
+        assert!(rendered.contains(
+            "srvstat_usage_window_percent{host=\"tux\",category=\"cpu\",stat=\"p95\"} 90\n"
+        ));
+        assert!(rendered.contains(
+            "srvstat_usage_window_percent{host=\"tux\",category=\"cpu\",stat=\"min\"} 5\n"
+        ));
+    }
+
+    #[test]
+    fn test_values() {
+        let writer = writer();
+        for (quantity, value) in [(Quantity::Load1, 0.25), (Quantity::Uptime, 3600.0)] {
+            let category = match quantity {
+                Quantity::Uptime => Category::Uptime,
+                _ => Category::Load,
+            };
+            writer
+                .write(Metric::Value("tux".to_string(), category, quantity, value))
+                .unwrap();
+        }
+        let rendered = writer.render(Instant::now());
+        assert!(rendered.contains(
+            "# HELP srvstat_load1 Load average over 1 minute.\n\
+             # TYPE srvstat_load1 gauge\n\
+             srvstat_load1{host=\"tux\",category=\"load\"} 0.25\n"
+        ));
+        assert!(
+            rendered.contains("srvstat_uptime_seconds{host=\"tux\",category=\"uptime\"} 3600\n")
+        );
+    }
+
+    #[test]
+    fn test_network_values() {
+        let writer = writer();
+        let interface = Category::Interface("eth0".to_string());
+        for (quantity, value) in [(Quantity::RxBytes, 1500.5), (Quantity::RxErrors, 3.0)] {
+            writer
+                .write(Metric::Value(
+                    "tux".to_string(),
+                    interface.clone(),
+                    quantity,
+                    value,
+                ))
+                .unwrap();
+        }
+        let rendered = writer.render(Instant::now());
+        assert!(rendered.contains(
+            "# TYPE srvstat_rx_bytes_per_second gauge\n\
+             srvstat_rx_bytes_per_second{host=\"tux\",category=\"interface\",interface=\"eth0\"} 1500.5\n"
+        ));
+        assert!(rendered.contains(
+            "# TYPE srvstat_rx_errors_total counter\n\
+             srvstat_rx_errors_total{host=\"tux\",category=\"interface\",interface=\"eth0\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_temperature() {
+        let writer = writer();
+        writer
+            .write(Metric::Value(
+                "tux".to_string(),
+                Category::Sensor("coretemp Core 0".to_string()),
+                Quantity::Temperature,
+                45.5,
+            ))
+            .unwrap();
+        let rendered = writer.render(Instant::now());
+        assert!(rendered.contains(
+            "srvstat_temperature_celsius{host=\"tux\",category=\"sensor\",sensor=\"coretemp Core 0\"} 45.5\n"
+        ));
+    }
+
+    #[test]
+    fn test_watched_values() {
+        let writer = writer();
+        let watched = Category::Watched("nginx".to_string());
+        for (quantity, value) in [(Quantity::Running, 1.0), (Quantity::ProcessMemory, 4096.0)] {
+            writer
+                .write(Metric::Value(
+                    "tux".to_string(),
+                    watched.clone(),
+                    quantity,
+                    value,
+                ))
+                .unwrap();
+        }
+        let rendered = writer.render(Instant::now());
+        assert!(rendered
+            .contains("srvstat_running{host=\"tux\",category=\"watched\",watch=\"nginx\"} 1\n"));
+        assert!(rendered.contains(
+            "srvstat_process_memory_bytes{host=\"tux\",category=\"watched\",watch=\"nginx\"} 4096\n"
+        ));
+    }
+
+    #[test]
+    fn test_top_processes() {
+        let writer = writer();
+        let process = |name: &str, pid| ProcessUsage {
+            name: name.to_string(),
+            pid,
+            cpu: 1.5,
+            memory: 4096,
+        };
+        let rankings = [
+            vec![process("postgres", 42), process("bash", 7)],
+            vec![process("cargo", 99)],
+        ];
+        for processes in rankings {
+            writer
+                .write(Metric::Top("tux".to_string(), Category::Memory, processes))
+                .unwrap();
+        }
+        let rendered = writer.render(Instant::now());
+        assert!(rendered.contains(
+            "# TYPE srvstat_top_process_memory_bytes gauge\n\
+             srvstat_top_process_memory_bytes{host=\"tux\",category=\"processes\",rank=\"1\",process=\"cargo\",pid=\"99\"} 4096\n"
+        ));
+        assert!(!rendered.contains("postgres"));
+        assert!(!rendered.contains("bash"));
+    }
+
+    #[test]
+    fn test_stale_series_expire() {
+        let writer = writer();
+        let home = Category::Filesystem("/home".to_string());
+        writer
+            .write(Metric::Used("tux".to_string(), home, 25, 100))
+            .unwrap();
+        let written = Instant::now();
+        writer
+            .write(Metric::Percent(
+                "tux".to_string(),
+                Category::Cpu,
+                Percentage(12),
+            ))
+            .unwrap();
+
+        assert!(writer.render(written).contains("mount=\"/home\""));
+        // Only the CPU usage is written again
+        writer
+            .write(Metric::Percent(
+                "tux".to_string(),
+                Category::Cpu,
+                Percentage(13),
+            ))
+            .unwrap();
+        let rendered = writer.render(written + Duration::from_secs(60));
+        assert!(!rendered.contains("/home"));
+        assert!(rendered.contains("srvstat_usage_percent{host=\"tux\",category=\"cpu\"} 13\n"));
+    }
+
+    #[test]
+    fn test_escape_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let writer = writer();
+        writer
+            .write(Metric::Percent(
+                "tux".to_string(),
+                Category::Memory,
+                Percentage(42),
+            ))
+            .unwrap();
+
+        let response = get(&writer, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("srvstat_usage_percent{host=\"tux\",category=\"memory\"} 42\n"));
+
+        assert!(get(&writer, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_stalled_client() {
+        let writer = writer();
+        // Connected without sending anything
+        let _stalled = TcpStream::connect(writer.local_addr()).unwrap();
+        let started = Instant::now();
+        assert!(get(&writer, "/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < IO_TIMEOUT);
+    }
+}